@@ -1,19 +1,39 @@
+#![allow(unexpected_cfgs)]
 #![no_std]
 
-#[panic_handler]
-fn panic(_info: &core::panic::PanicInfo) -> ! {
-    loop {}
-}
-
 use soroban_sdk::{
-    contract, contracterror, contractimpl, contracttype, symbol_short, token, vec, Address, Env,
-    Vec,
+    contract, contractclient, contracterror, contractimpl, contractmeta, contracttype, log,
+    symbol_short, token, xdr::ToXdr, Address, BytesN, Env, IntoVal, InvokeError, Map, Symbol,
+    TryFromVal, Val, Vec,
 };
 
+#[cfg(any(test, feature = "testutils"))]
+pub mod testutils;
+pub mod vesting;
+
+contractmeta!(key = "Description", val = "Streaming token grants");
+
+/// Package version, embedded as the `crate_version` meta entry. contractmeta! only takes
+/// literals, so the entry is encoded here from the version Cargo passes at build time.
+#[doc(hidden)]
+#[cfg_attr(target_family = "wasm", link_section = "contractmetav0")]
+pub static CRATE_VERSION_META: [u8; meta_entry_len("crate_version", env!("CARGO_PKG_VERSION"))] =
+    meta_entry_xdr("crate_version", env!("CARGO_PKG_VERSION"));
+
 #[contract]
 pub struct GrantContract;
 
+/// Fixed-point scale of prices returned by a PriceOracle (7 decimals, as for Stellar assets).
+pub const PRICE_SCALE: i128 = 10_000_000;
+
+/// Interface a grant's price oracle must implement.
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    /// Price of one unit of `asset` in units of `reference`, scaled by PRICE_SCALE.
+    fn price(env: Env, asset: Address, reference: Address) -> i128;
+}
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[contracttype]
 pub enum GrantStatus {
     Active,
@@ -21,9 +41,54 @@ pub enum GrantStatus {
     Cancelled,
 }
 
+/// (major, minor, patch) of this build, taken from the package version.
+pub const CONTRACT_VERSION: (u32, u32, u32) = parse_semver(env!("CARGO_PKG_VERSION"));
+
+/// Layout version of the data written to storage; migrations branch on the stored value.
+/// Bump this in any change that alters the storage layout.
+///
+/// 1: The original layout: Grant records and the GrantIds list in instance storage, with no
+///    indexes or token stats and no recorded SchemaVersion.
+/// 2: Every per-grant and per-address record and every index lives in persistent storage,
+///    each with its own TTL; instance storage only keeps configuration.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Approximate number of ledgers closed per day, at five seconds per ledger.
+const LEDGERS_PER_DAY: u32 = 17_280;
+
+/// A grant record, or the contract instance, whose TTL falls below this many ledgers is
+/// extended when accessed.
+const RECORD_TTL_THRESHOLD: u32 = 30 * LEDGERS_PER_DAY;
+
+/// TTL, in ledgers, that an accessed grant record or bumped instance is extended to, capped
+/// at the network's maximum.
+const RECORD_TTL_EXTEND_TO: u32 = 365 * LEDGERS_PER_DAY;
+
+/// Default cap on HistoryEntry records kept per grant.
+const DEFAULT_HISTORY_LIMIT: u32 = 50;
+
+/// Default limit on how far a timestamp-based vesting start may lie from the creation time,
+/// in either direction: 10 years in seconds.
+const DEFAULT_MAX_START_OFFSET: u64 = 10 * 365 * 24 * 60 * 60;
+
 /// 90 days in seconds (inactivity threshold for slash_inactive_grant).
 const INACTIVITY_THRESHOLD_SECS: u64 = 90 * 24 * 60 * 60; // 7_776_000
 
+/// Upper bound on the number of ids returned by a single index query.
+const MAX_PAGE_SIZE: u32 = 50;
+
+/// Maximum number of category tags on one grant.
+const MAX_TAGS: u32 = 5;
+
+/// Maximum number of tranches in one vesting grant.
+const MAX_TRANCHES: u32 = 10;
+
+/// Largest decimal scale a grant's amounts can be displayed in; 10^38 still fits in an i128.
+const MAX_AMOUNT_SCALE: u32 = 38;
+
+/// Maximum number of addresses in the admin multisig.
+const MAX_MULTISIG_ADMINS: u32 = 10;
+
 #[derive(Clone)]
 #[contracttype]
 pub struct Grant {
@@ -39,6 +104,420 @@ pub struct Grant {
     pub status: GrantStatus,
 }
 
+/// On-chain form of vesting::VestingCurve.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum VestingCurveData {
+    Linear,
+    Quadratic,
+}
+
+impl From<VestingCurveData> for vesting::VestingCurve {
+    fn from(curve: VestingCurveData) -> Self {
+        match curve {
+            VestingCurveData::Linear => vesting::VestingCurve::Linear,
+            VestingCurveData::Quadratic => vesting::VestingCurve::Quadratic,
+        }
+    }
+}
+
+impl From<vesting::VestingCurve> for VestingCurveData {
+    fn from(curve: vesting::VestingCurve) -> Self {
+        match curve {
+            vesting::VestingCurve::Linear => VestingCurveData::Linear,
+            vesting::VestingCurve::Quadratic => VestingCurveData::Quadratic,
+        }
+    }
+}
+
+/// On-chain form of vesting::Rounding.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum RoundingData {
+    Floor,
+    Ceil,
+}
+
+impl From<RoundingData> for vesting::Rounding {
+    fn from(rounding: RoundingData) -> Self {
+        match rounding {
+            RoundingData::Floor => vesting::Rounding::Floor,
+            RoundingData::Ceil => vesting::Rounding::Ceil,
+        }
+    }
+}
+
+impl From<vesting::Rounding> for RoundingData {
+    fn from(rounding: vesting::Rounding) -> Self {
+        match rounding {
+            vesting::Rounding::Floor => RoundingData::Floor,
+            vesting::Rounding::Ceil => RoundingData::Ceil,
+        }
+    }
+}
+
+/// On-chain form of vesting::Portion.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum PortionData {
+    Amount(u128),
+    Bps(u32),
+}
+
+impl From<PortionData> for vesting::Portion {
+    fn from(portion: PortionData) -> Self {
+        match portion {
+            PortionData::Amount(amount) => vesting::Portion::Amount(amount),
+            PortionData::Bps(bps) => vesting::Portion::Bps(bps),
+        }
+    }
+}
+
+impl From<vesting::Portion> for PortionData {
+    fn from(portion: vesting::Portion) -> Self {
+        match portion {
+            vesting::Portion::Amount(amount) => PortionData::Amount(amount),
+            vesting::Portion::Bps(bps) => PortionData::Bps(bps),
+        }
+    }
+}
+
+/// On-chain form of vesting::Segment.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ScheduleSegmentData {
+    pub start_offset: u64,
+    pub duration: u64,
+    pub portion: PortionData,
+}
+
+impl From<ScheduleSegmentData> for vesting::Segment {
+    fn from(segment: ScheduleSegmentData) -> Self {
+        vesting::Segment {
+            start_offset: segment.start_offset,
+            duration: segment.duration,
+            portion: segment.portion.into(),
+        }
+    }
+}
+
+impl From<vesting::Segment> for ScheduleSegmentData {
+    fn from(segment: vesting::Segment) -> Self {
+        ScheduleSegmentData {
+            start_offset: segment.start_offset,
+            duration: segment.duration,
+            portion: segment.portion.into(),
+        }
+    }
+}
+
+/// On-chain form of vesting::Schedule. Unlike the math form it is not validated, so it is
+/// converted with TryFrom, which applies the checks of vesting::Schedule::new.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct VestingScheduleData {
+    pub segments: Vec<ScheduleSegmentData>,
+}
+
+impl VestingScheduleData {
+    pub fn from_schedule(env: &Env, schedule: &vesting::Schedule) -> Self {
+        let mut segments = Vec::new(env);
+        for segment in schedule.segments() {
+            segments.push_back(ScheduleSegmentData::from(*segment));
+        }
+        VestingScheduleData { segments }
+    }
+}
+
+impl TryFrom<&VestingScheduleData> for vesting::Schedule {
+    type Error = vesting::ScheduleError;
+
+    fn try_from(data: &VestingScheduleData) -> Result<Self, Self::Error> {
+        if data.segments.len() as usize > vesting::MAX_SEGMENTS {
+            return Err(vesting::ScheduleError::TooManySegments);
+        }
+        let mut segments = [vesting::Segment {
+            start_offset: 0,
+            duration: 0,
+            portion: vesting::Portion::Amount(0),
+        }; vesting::MAX_SEGMENTS];
+        let len = data.segments.len() as usize;
+        for (slot, segment) in segments.iter_mut().zip(data.segments.iter()) {
+            *slot = segment.into();
+        }
+        vesting::Schedule::new(&segments[..len])
+    }
+}
+
+/// Clock a vesting schedule is measured against.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum TimeBasis {
+    /// Ledger timestamps, in seconds.
+    Timestamp,
+    /// Ledger sequence numbers, in ledgers.
+    LedgerSequence,
+}
+
+/// One independently scheduled portion of a vesting grant created with
+/// create_grant_vested_tranches. Times are in the grant's time basis.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct VestingTranche {
+    pub amount: i128,
+    pub start: u64,
+    pub duration: u64,
+    /// Time after this tranche's `start` before any of it can be claimed.
+    pub cliff: u64,
+}
+
+/// A grant that vests on a fixed curve, linear by default, with an optional cliff and is paid
+/// out by claim_vested. Shares the id space with streaming grants. Every time field is in the
+/// units of `time_basis`, which is fixed at creation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct VestingSchedule {
+    pub recipient: Address,
+    pub total_amount: i128,
+    pub start: u64,
+    pub duration: u64,
+    /// Time after `start` before anything can be claimed.
+    pub cliff: u64,
+    /// Cumulative amount transferred to the recipient.
+    pub claimed: i128,
+    pub status: GrantStatus,
+    /// Set on cancellation; nothing vests after this time.
+    pub stopped_at: Option<u64>,
+    /// Time after `start` spent paused, excluding a pause still in progress.
+    pub total_paused_seconds: u64,
+    /// Set while the schedule is paused by pause_vesting.
+    pub paused_at: Option<u64>,
+    /// Linear unless changed with set_vesting_curve before the schedule starts.
+    pub curve: VestingCurveData,
+    /// Floor unless changed with set_vesting_rounding before the schedule starts.
+    pub rounding: RoundingData,
+    pub time_basis: TimeBasis,
+    /// Portions vesting on their own schedules, fixed at creation. Empty for a single
+    /// schedule given by `start`, `duration` and `cliff`; otherwise those span the tranches.
+    pub tranches: Vec<VestingTranche>,
+}
+
+/// Snapshot of how far a grant has streamed, for dashboards.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct GrantProgress {
+    pub total_amount: i128,
+    /// Withdrawn plus currently claimable.
+    pub released_amount: i128,
+    pub withdrawn_amount: i128,
+    /// released_amount / total_amount in basis points, rounded down.
+    pub released_bps: u32,
+}
+
+/// Running totals for every grant denominated in one token.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[contracttype]
+pub struct TokenStats {
+    /// Outstanding obligations: total_amount - withdrawn, or only the vested claimable
+    /// remainder once a grant is cancelled.
+    pub escrowed: i128,
+    /// Cumulative accrued amount, as of each grant's last persisted settlement.
+    pub released: i128,
+    /// Cumulative amount withdrawn by recipients.
+    pub withdrawn: i128,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum HistoryAction {
+    Created,
+    Withdrawn,
+    RateUpdated,
+    Rescheduled,
+    RecipientChanged,
+    Cancelled,
+    Declined,
+    Slashed,
+    Paused,
+    Resumed,
+    Revoked,
+    TagsUpdated,
+    TermsLocked,
+    RefundClaimed,
+    WithdrawLimitUpdated,
+    HookUpdated,
+    AdminWithdrawsUpdated,
+    OracleUpdated,
+    TransferLockUpdated,
+    CurveUpdated,
+    RoundingUpdated,
+}
+
+/// One state-changing call against a grant. `amount` is the withdrawn amount, new flow rate
+/// or refund claimed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct HistoryEntry {
+    pub action: HistoryAction,
+    /// The authorizing address, or the contract itself for permissionless calls (slash_inactive_grant).
+    pub actor: Address,
+    pub timestamp: u64,
+    pub amount: Option<i128>,
+}
+
+/// Why ClaimableDetails.amount is what it is.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum ClaimableReason {
+    /// The full accrued balance is covered by the contract's token balance.
+    Available,
+    /// Nothing has accrued since the last withdrawal.
+    NothingAccrued,
+    /// The contract holds less of the grant token than has accrued.
+    Underfunded,
+    /// The contract is paused and withdrawals from this grant are not exempt.
+    Paused,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct ClaimableDetails {
+    /// Accrued balance, clamped to the contract's grant token balance.
+    pub amount: i128,
+    /// Accrued balance before clamping.
+    pub accrued: i128,
+    pub contract_balance: i128,
+    pub reason: ClaimableReason,
+}
+
+/// Outcome of a dry-run withdrawal. On failure `error` holds the Error code the real call
+/// would return and the remaining fields are zero.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct WithdrawPreview {
+    pub error: Option<u32>,
+    /// Amount that would move from claimable to withdrawn.
+    pub amount: i128,
+    /// Grant's withdrawn total after the call.
+    pub withdrawn: i128,
+    pub completes: bool,
+}
+
+/// Result of withdraw_with_receipt and claim_vested_with_receipt, for relayers that need to
+/// audit what arrived.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct WithdrawReceipt {
+    /// Amount the contract sent.
+    pub requested: i128,
+    /// Increase in the recipient's token balance; less than `requested` for fee-on-transfer
+    /// tokens.
+    pub transferred: i128,
+    /// Grant's withdrawn total, or schedule's claimed total, after the call.
+    pub new_released: i128,
+    pub timestamp: u64,
+}
+
+/// Cap on how much a grant's recipient may withdraw within each `period` seconds.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct WithdrawLimit {
+    pub max_per_period: i128,
+    pub period: u64,
+}
+
+/// Withdrawals counted against a WithdrawLimit since `start`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct WithdrawWindow {
+    pub start: u64,
+    pub withdrawn: i128,
+}
+
+/// Extra payout escrowed at creation and released to the recipient when the grant completes.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct GrantBonus {
+    pub token: Address,
+    pub amount: i128,
+}
+
+/// What remains of a grant once archive_grant has deleted it; keeps the id from being reused.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct GrantTombstone {
+    pub status: GrantStatus,
+    /// sha256 of the XDR-encoded GrantExport published in the `archived` event.
+    pub export_hash: BytesN<32>,
+}
+
+/// Addresses that approve AdminActions, and how many of them must approve one before it runs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub struct AdminMultisig {
+    pub admins: Vec<Address>,
+    pub threshold: u32,
+}
+
+/// A contract-wide operation run by execute_admin_action once the multisig threshold approves it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[contracttype]
+pub enum AdminAction {
+    /// Pauses (true) or unpauses (false) every entrypoint that moves funds in or out, except
+    /// those on the pause exemption list.
+    SetPaused(bool),
+    /// Replaces the pause exemption list with these entrypoint names.
+    SetPauseExemptions(Vec<Symbol>),
+    /// Replaces the contract's code with the uploaded wasm of the given hash.
+    Upgrade(BytesN<32>),
+    /// Replaces the multisig itself; approvals already given by addresses no longer in the
+    /// set stop counting.
+    SetMultisig(AdminMultisig),
+}
+
+/// The stored record of a grant of either kind.
+#[derive(Clone)]
+#[contracttype]
+pub enum GrantRecord {
+    /// Grant state settled to the current ledger time.
+    Streaming(Grant),
+    Vesting(VestingSchedule),
+}
+
+/// Everything stored about one grant plus the contract settings that affect it, for audits and
+/// migration to a future contract version.
+#[derive(Clone)]
+#[contracttype]
+pub struct GrantExport {
+    pub grant_id: u64,
+    pub grant: GrantRecord,
+    /// Retained HistoryEntry records, oldest first.
+    pub history: Vec<HistoryEntry>,
+    /// The grant's hook contract, if it has one. Optional records are exported as lists of at
+    /// most one entry.
+    pub hook: Vec<Address>,
+    pub tags: Vec<Symbol>,
+    pub withdraw_limit: Vec<WithdrawLimit>,
+    /// Current WithdrawWindow of a rate-limited grant.
+    pub withdraw_window: Vec<WithdrawWindow>,
+    pub admin_withdraws: bool,
+    pub oracle: Vec<Address>,
+    pub grantee_transfer_locked: bool,
+    /// Bonus still escrowed for the grant.
+    pub bonus: Vec<GrantBonus>,
+    pub refund_owed: i128,
+    /// Who claim_refund pays the refund to, if not the treasury.
+    pub refund_to: Vec<Address>,
+    pub created_at: u64,
+    pub amount_scale: u32,
+    pub max_fee_bps: Option<u32>,
+    pub released_by_token: Map<Address, i128>,
+    pub terms_locked: bool,
+    pub history_limit: u32,
+    pub dust_threshold: i128,
+    pub prune_cancelled: bool,
+}
+
 #[derive(Clone)]
 #[contracttype]
 enum DataKey {
@@ -47,9 +526,97 @@ enum DataKey {
     GrantToken,
     /// DAO treasury; slashed funds are sent here.
     Treasury,
-    /// All grant IDs ever created (for computing total_allocated_funds).
+    /// All grant IDs ever created, in creation order.
     GrantIds,
     Grant(u64),
+    /// Grant IDs created under a given admin.
+    AdminGrants(Address),
+    /// Grant IDs whose current recipient is the given address.
+    GranteeGrants(Address),
+    /// When true, cancelled grants are dropped from the per-admin index.
+    PruneCancelled,
+    /// Shortfall (in token units) below which a grant is treated as fully paid.
+    DustThreshold,
+    /// Aggregate TokenStats per token.
+    TokenStats(Address),
+    /// Next id handed out by create_grant_auto.
+    NextGrantId,
+    /// Contract notified via `on_withdraw(grant_id, amount)` after each withdrawal.
+    GrantHook(u64),
+    /// Bounded action log per grant, oldest first.
+    History(u64),
+    /// Maximum entries kept per grant history; older entries are dropped.
+    HistoryLimit,
+    /// Present once a finished grant has been archived.
+    Archived(u64),
+    /// VestingSchedule for grants created with create_grant_vested.
+    Vesting(u64),
+    /// Optional WithdrawLimit for a grant.
+    WithdrawLimit(u64),
+    /// Current WithdrawWindow for a rate-limited grant.
+    WithdrawWindow(u64),
+    /// When true, withdrawals from the grant are authorized by the admin instead of the recipient.
+    AdminWithdraws(u64),
+    /// PriceOracle used to value a grant in a reference currency.
+    GrantOracle(u64),
+    /// Category tags attached to a grant.
+    GrantTags(u64),
+    /// Grant IDs carrying the given tag.
+    TagGrants(Symbol),
+    /// Grant IDs whose stored status is the given status.
+    GrantsByStatus(GrantStatus),
+    /// SCHEMA_VERSION at the time storage was last written or migrated.
+    SchemaVersion,
+    /// When true, the recipient may not hand the grant over with transfer_grantee_rights.
+    GranteeTransferLocked(u64),
+    /// Unpaid GrantBonus for a grant; removed once paid or refunded.
+    GrantBonus(u64),
+    /// Unvested remainder of a cancelled grant awaiting claim_refund.
+    RefundOwed(u64),
+    /// Ledger timestamp at which a grant was created.
+    CreatedAt(u64),
+    /// Limit on how far a vesting start may be from the creation time; absent means the
+    /// default, stored None means unlimited.
+    MaxStartOffset,
+    /// ed25519 public key whose signatures authorize withdraw_signed for a grantee.
+    WithdrawSigner(Address),
+    /// Last nonce a grantee used with withdraw_signed.
+    WithdrawNonce(Address),
+    /// Decimal places the grant's amounts are displayed with; absent means 0.
+    AmountScale(u64),
+    /// Whether the admin may also be a grant's recipient; absent means true.
+    AllowSelfGrants,
+    /// GrantTombstone left in place of a grant deleted by archive_grant.
+    Tombstone(u64),
+    /// Number of grants, streaming or vesting, that reached Completed while held by an address.
+    CompletedCount(Address),
+    /// AdminMultisig allowed to run AdminActions.
+    AdminMultisig,
+    /// Multisig admins that have approved the AdminAction with this sha256 of its XDR.
+    ActionApprovals(BytesN<32>),
+    /// When true, entrypoints that move funds fail unless they are pause-exempt.
+    Paused,
+    /// Entrypoint names still callable while paused; absent means the default list.
+    PauseExemptions,
+    /// Largest share, in basis points, of a vesting claim the token may withhold as a fee.
+    MaxFeeBps(u64),
+    /// Share of a new grant's total, in basis points, the contract must already hold; absent
+    /// means 0.
+    MinFundingBps,
+    /// Map of token to the amount of it a grant has released to its recipient.
+    ReleasedByToken(u64),
+    /// Set once lock_terms has frozen a grant's amounts and schedule.
+    TermsLocked(u64),
+    /// Decimals reported by the grant token, cached once it has been validated.
+    TokenDecimals,
+    /// When true, grant creation does not check that the grant token answers decimals().
+    SkipTokenValidation,
+    /// Sum of every grant's RefundOwed.
+    RefundsOwed,
+    /// Who claim_refund pays a grant's RefundOwed to; absent means the treasury.
+    RefundTo(u64),
+    /// Sum of the unpaid GrantBonus amounts escrowed in a token.
+    PendingBonuses(Address),
 }
 
 #[contracterror]
@@ -60,6 +627,8 @@ pub enum Error {
     AlreadyInitialized = 2,
     NotAuthorized = 3,
     GrantNotFound = 4,
+    /// The id is held by another grant in any state. Ids of cancelled or completed grants
+    /// are never reused, so their records stay intact for audit.
     GrantAlreadyExists = 5,
     InvalidRate = 6,
     InvalidAmount = 7,
@@ -69,6 +638,114 @@ pub enum Error {
     RescueWouldViolateAllocated = 10,
     /// Grant has been active (claimed) within the inactivity threshold; cannot slash yet.
     GrantNotInactive = 11,
+    /// Withdrawal would exceed the grant's per-period withdrawal limit.
+    RateLimited = 12,
+    /// More than MAX_TAGS tags were given for a grant.
+    TooManyTags = 13,
+    /// A batch call was given more than MAX_PAGE_SIZE ids.
+    BatchTooLarge = 14,
+    /// The grant has no price oracle configured.
+    OracleNotSet = 15,
+    /// Nothing has vested since the last claim.
+    NothingToClaim = 16,
+    /// A vesting start lies further from the current time than the configured maximum offset.
+    StartOutOfRange = 17,
+    /// A signed withdrawal's nonce is not above the grantee's last used nonce.
+    InvalidNonce = 18,
+    /// More than MAX_TRANCHES tranches, or vesting::MAX_SEGMENTS schedule segments, were given
+    /// for a vesting grant.
+    TooManyTranches = 19,
+    /// The contract holds less of the grant token than a payout needs.
+    InsufficientFunding = 20,
+    /// Self-grants are disabled and the grant's recipient would be the admin.
+    SelfGrantNotAllowed = 21,
+    /// The grant was archived and its data deleted; only its tombstone remains.
+    GrantArchived = 22,
+    /// The multisig threshold is zero, above the number of admins, or the admin set is invalid.
+    InvalidThreshold = 23,
+    /// The contract is paused by an AdminAction and the entrypoint is not pause-exempt.
+    ContractPaused = 24,
+    /// The token withheld more of a payout than the grant's max_fee_bps allows.
+    ExcessiveFee = 25,
+    /// The grant token address does not answer the standard token interface.
+    InvalidToken = 26,
+    /// A withdrawal asked for more than the grant has released and not yet withdrawn.
+    InsufficientReleasedBalance = 27,
+    /// The grant's terms were locked with lock_terms and can no longer be changed.
+    TermsLocked = 28,
+    /// A token transfer's balance changes were inconsistent with the amount sent.
+    TransferFailed = 29,
+}
+
+/// Parses "MAJOR.MINOR.PATCH", ignoring any pre-release or build suffix.
+const fn parse_semver(version: &str) -> (u32, u32, u32) {
+    let bytes = version.as_bytes();
+    let mut parts = [0_u32; 3];
+    let mut part = 0;
+    let mut i = 0;
+    while i < bytes.len() && part < 3 {
+        let b = bytes[i];
+        if b == b'.' {
+            part += 1;
+        } else if b.is_ascii_digit() {
+            parts[part] = parts[part] * 10 + (b - b'0') as u32;
+        } else {
+            break;
+        }
+        i += 1;
+    }
+    (parts[0], parts[1], parts[2])
+}
+
+/// Length of a contract meta entry for `key` and `val` in XDR: the ScMetaEntry discriminant,
+/// then each string as a length prefix and its bytes padded to four.
+const fn meta_entry_len(key: &str, val: &str) -> usize {
+    4 + 4 + key.len().next_multiple_of(4) + 4 + val.len().next_multiple_of(4)
+}
+
+/// XDR of ScMetaEntry::ScMetaV0 { key, val }, the form contractmeta! embeds.
+const fn meta_entry_xdr<const N: usize>(key: &str, val: &str) -> [u8; N] {
+    let mut out = [0_u8; N];
+    // The ScMetaV0 discriminant is 0, so the first word stays zeroed.
+    let mut at = 4;
+    let strings = [key.as_bytes(), val.as_bytes()];
+    let mut s = 0;
+    while s < strings.len() {
+        let bytes = strings[s];
+        let len = (bytes.len() as u32).to_be_bytes();
+        let mut i = 0;
+        while i < 4 {
+            out[at + i] = len[i];
+            i += 1;
+        }
+        at += 4;
+        let mut i = 0;
+        while i < bytes.len() {
+            out[at + i] = bytes[i];
+            i += 1;
+        }
+        at += bytes.len().next_multiple_of(4);
+        s += 1;
+    }
+    out
+}
+
+/// Checks that the threshold is between 1 and the number of admins, and that the admins are
+/// distinct and at most MAX_MULTISIG_ADMINS.
+fn validate_multisig(multisig: &AdminMultisig) -> Result<(), Error> {
+    let admins = &multisig.admins;
+    if multisig.threshold == 0
+        || multisig.threshold > admins.len()
+        || admins.len() > MAX_MULTISIG_ADMINS
+    {
+        return Err(Error::InvalidThreshold);
+    }
+    for (i, admin) in admins.iter().enumerate() {
+        if admins.first_index_of(&admin) != Some(i as u32) {
+            return Err(Error::InvalidThreshold);
+        }
+    }
+    Ok(())
 }
 
 fn read_admin(env: &Env) -> Result<Address, Error> {
@@ -78,212 +755,2729 @@ fn read_admin(env: &Env) -> Result<Address, Error> {
         .ok_or(Error::NotInitialized)
 }
 
-fn require_admin_auth(env: &Env) -> Result<(), Error> {
+fn require_admin_auth(env: &Env) -> Result<Address, Error> {
     let admin = read_admin(env)?;
     admin.require_auth();
+    Ok(admin)
+}
+
+/// Fails with ContractPaused if the contract is paused and `function` is not on the pause
+/// exemption list.
+fn ensure_not_paused(env: &Env, function: &str) -> Result<(), Error> {
+    let paused = env
+        .storage()
+        .instance()
+        .get(&DataKey::Paused)
+        .unwrap_or(false);
+    if paused && !read_pause_exemptions(env).contains(Symbol::new(env, function)) {
+        return Err(Error::ContractPaused);
+    }
     Ok(())
 }
 
-fn read_grant(env: &Env, grant_id: u64) -> Result<Grant, Error> {
+/// Entrypoints that keep working while the contract is paused. Unless changed with
+/// AdminAction::SetPauseExemptions, the ones that let funds escape: cancel_grant,
+/// claim_refund, revoke_vesting and rescue_tokens.
+fn read_pause_exemptions(env: &Env) -> Vec<Symbol> {
     env.storage()
         .instance()
-        .get(&DataKey::Grant(grant_id))
-        .ok_or(Error::GrantNotFound)
+        .get(&DataKey::PauseExemptions)
+        .unwrap_or_else(|| {
+            Vec::from_array(
+                env,
+                [
+                    Symbol::new(env, "cancel_grant"),
+                    Symbol::new(env, "claim_refund"),
+                    Symbol::new(env, "revoke_vesting"),
+                    Symbol::new(env, "rescue_tokens"),
+                ],
+            )
+        })
 }
 
-fn write_grant(env: &Env, grant_id: u64, grant: &Grant) {
-    env.storage().instance().set(&DataKey::Grant(grant_id), grant);
+fn ensure_terms_unlocked(env: &Env, grant_id: u64) -> Result<(), Error> {
+    if has_record(env, &DataKey::TermsLocked(grant_id)) {
+        return Err(Error::TermsLocked);
+    }
+    Ok(())
 }
 
-fn read_grant_token(env: &Env) -> Result<Address, Error> {
-    env.storage()
+/// Fails with SelfGrantNotAllowed if `recipient` is the admin and self-grants are disabled.
+fn check_self_grant(env: &Env, admin: &Address, recipient: &Address) -> Result<(), Error> {
+    let allowed: bool = env
+        .storage()
         .instance()
-        .get(&DataKey::GrantToken)
-        .ok_or(Error::NotInitialized)
+        .get(&DataKey::AllowSelfGrants)
+        .unwrap_or(true);
+    if !allowed && admin == recipient {
+        return Err(Error::SelfGrantNotAllowed);
+    }
+    Ok(())
 }
 
-fn read_treasury(env: &Env) -> Result<Address, Error> {
-    env.storage()
-        .instance()
-        .get(&DataKey::Treasury)
-        .ok_or(Error::NotInitialized)
+/// Reads a record: per-grant or per-address data, or an index. These live in persistent
+/// storage, each with its own TTL, so instance storage only holds the fixed-size
+/// configuration. Contracts deployed under schema 1 keep their grants and id list in instance
+/// storage until migrate_storage moves them, so reads fall back there.
+fn read_record<V: TryFromVal<Env, Val>>(env: &Env, key: &DataKey) -> Option<V> {
+    if let Some(value) = env.storage().persistent().get(key) {
+        extend_record_ttl(env, key, RECORD_TTL_THRESHOLD, RECORD_TTL_EXTEND_TO);
+        return Some(value);
+    }
+    env.storage().instance().get(key)
 }
 
-fn read_grant_ids(env: &Env) -> Vec<u64> {
+/// Extends a persistent record's TTL to `extend_to` ledgers, capped at the network maximum,
+/// if it is below `threshold`.
+fn extend_record_ttl(env: &Env, key: &DataKey, threshold: u32, extend_to: u32) {
+    let extend_to = extend_to.min(env.storage().max_ttl());
     env.storage()
-        .instance()
-        .get(&DataKey::GrantIds)
-        .unwrap_or_else(|| Vec::new(env))
-}
-
-/// Sum of (total_amount - withdrawn) for all active grants. Represents tokens that must remain in the contract.
-fn total_allocated_funds(env: &Env) -> Result<i128, Error> {
-    let mut total = 0_i128;
-    let ids = read_grant_ids(env);
-    for i in 0..ids.len() {
-        let grant_id = ids.get(i).unwrap();
-        if let Some(grant) = env.storage().instance().get::<_, Grant>(&DataKey::Grant(grant_id)) {
-            if grant.status == GrantStatus::Active {
-                let remaining = grant
-                    .total_amount
-                    .checked_sub(grant.withdrawn)
-                    .ok_or(Error::MathOverflow)?;
-                total = total.checked_add(remaining).ok_or(Error::MathOverflow)?;
-            }
-        }
+        .persistent()
+        .extend_ttl(key, threshold.min(extend_to), extend_to);
+}
+
+fn has_record(env: &Env, key: &DataKey) -> bool {
+    env.storage().persistent().has(key) || env.storage().instance().has(key)
+}
+
+/// Writes a record to persistent storage, extending its TTL and dropping any schema 1 copy
+/// left in instance storage.
+fn write_record<V: IntoVal<Env, Val>>(env: &Env, key: &DataKey, value: &V) {
+    env.storage().persistent().set(key, value);
+    extend_record_ttl(env, key, RECORD_TTL_THRESHOLD, RECORD_TTL_EXTEND_TO);
+    env.storage().instance().remove(key);
+}
+
+fn remove_record(env: &Env, key: &DataKey) {
+    env.storage().persistent().remove(key);
+    env.storage().instance().remove(key);
+}
+
+/// Every key holding data that belongs to one grant, other than the Archived flag and
+/// Tombstone archive_grant leaves behind.
+fn grant_record_keys(grant_id: u64) -> [DataKey; 18] {
+    [
+        DataKey::Grant(grant_id),
+        DataKey::Vesting(grant_id),
+        DataKey::GrantHook(grant_id),
+        DataKey::History(grant_id),
+        DataKey::WithdrawLimit(grant_id),
+        DataKey::WithdrawWindow(grant_id),
+        DataKey::AdminWithdraws(grant_id),
+        DataKey::GrantOracle(grant_id),
+        DataKey::GrantTags(grant_id),
+        DataKey::GranteeTransferLocked(grant_id),
+        DataKey::GrantBonus(grant_id),
+        DataKey::RefundOwed(grant_id),
+        DataKey::RefundTo(grant_id),
+        DataKey::CreatedAt(grant_id),
+        DataKey::AmountScale(grant_id),
+        DataKey::MaxFeeBps(grant_id),
+        DataKey::ReleasedByToken(grant_id),
+        DataKey::TermsLocked(grant_id),
+    ]
+}
+
+/// A record as a list of at most one value, for exporting optional records.
+fn read_optional_record<V>(env: &Env, key: &DataKey) -> Vec<V>
+where
+    V: IntoVal<Env, Val> + TryFromVal<Env, Val>,
+{
+    let mut values = Vec::new(env);
+    if let Some(value) = read_record(env, key) {
+        values.push_back(value);
     }
-    Ok(total)
+    values
 }
 
+/// Moves a record written under schema 1 from instance to persistent storage, if present.
+fn migrate_record(env: &Env, key: &DataKey) {
+    if let Some(value) = env.storage().instance().get::<_, Val>(key) {
+        write_record(env, key, &value);
+    }
+}
 
+fn read_grant(env: &Env, grant_id: u64) -> Result<Grant, Error> {
+    read_record(env, &DataKey::Grant(grant_id)).ok_or_else(|| missing_grant_error(env, grant_id))
+}
 
-fn settle_grant(grant: &mut Grant, now: u64) -> Result<(), Error> {
-    if now < grant.last_update_ts {
+/// Error for a grant whose data is gone: GrantArchived if archive_grant deleted it, otherwise
+/// GrantNotFound.
+fn missing_grant_error(env: &Env, grant_id: u64) -> Error {
+    if has_record(env, &DataKey::Tombstone(grant_id)) {
+        Error::GrantArchived
+    } else {
+        Error::GrantNotFound
+    }
+}
+
+/// Fails like read_grant unless `grant_id` is a live streaming or vesting grant.
+fn ensure_grant_exists(env: &Env, grant_id: u64) -> Result<(), Error> {
+    if has_record(env, &DataKey::Grant(grant_id)) || has_record(env, &DataKey::Vesting(grant_id)) {
+        Ok(())
+    } else {
+        Err(missing_grant_error(env, grant_id))
+    }
+}
+
+/// Persists a grant and emits transition side effects by comparing against the stored copy.
+fn write_grant(env: &Env, grant_id: u64, grant: &Grant) -> Result<(), Error> {
+    let key = DataKey::Grant(grant_id);
+    // Until migrate_storage has added a schema 1 grant to the indexes and token stats, it is
+    // read-only.
+    if env.storage().instance().has(&key) {
         return Err(Error::InvalidState);
     }
+    let previous: Option<Grant> = read_record(env, &key);
+    write_record(env, &key, grant);
+
+    update_token_stats(env, previous.as_ref(), grant)?;
+
+    let previous_status = previous.as_ref().map(|p| p.status);
+    if previous_status != Some(grant.status) {
+        if let Some(status) = previous_status {
+            index_remove(env, &DataKey::GrantsByStatus(status), grant_id);
+        }
+        index_add(env, &DataKey::GrantsByStatus(grant.status), grant_id);
+    }
+
+    let was_completed = matches!(previous, Some(ref p) if p.status == GrantStatus::Completed);
+    if grant.status == GrantStatus::Completed && !was_completed {
+        let released = grant.withdrawn.saturating_add(grant.claimable);
+        on_grant_completed(
+            env,
+            grant_id,
+            &grant.recipient,
+            grant.total_amount,
+            released,
+        );
+    }
+    Ok(())
+}
+
+/// Counts a newly completed grant, streaming or vesting, for its recipient and publishes
+/// `("completed", grant_id)` with (grant_id, total_amount, released, timestamp).
+fn on_grant_completed(
+    env: &Env,
+    grant_id: u64,
+    recipient: &Address,
+    total_amount: i128,
+    released: i128,
+) {
+    record_completion(env, recipient);
+    env.events().publish(
+        (symbol_short!("completed"), grant_id),
+        (grant_id, total_amount, released, env.ledger().timestamp()),
+    );
+}
+
+/// Counts a grant completed by `grantee` towards their completed-grant total.
+fn record_completion(env: &Env, grantee: &Address) {
+    let key = DataKey::CompletedCount(grantee.clone());
+    let count: u64 = read_record(env, &key).unwrap_or(0);
+    write_record(env, &key, &count.saturating_add(1));
+}
+
+/// (escrowed, released, withdrawn) contribution of a single grant to its token's stats.
+fn stats_contribution(grant: &Grant) -> Result<(i128, i128, i128), Error> {
+    let escrowed = if grant.status == GrantStatus::Cancelled {
+        grant.claimable
+    } else {
+        grant
+            .total_amount
+            .checked_sub(grant.withdrawn)
+            .ok_or(Error::MathOverflow)?
+    };
+    let released = grant
+        .withdrawn
+        .checked_add(grant.claimable)
+        .ok_or(Error::MathOverflow)?;
+    Ok((escrowed, released, grant.withdrawn))
+}
+
+/// (escrowed, released, withdrawn) contribution of a single vesting schedule to its token's
+/// stats. Vesting only settles on claims, so released is the claimed amount until the
+/// schedule is stopped, which fixes the vested total.
+fn vesting_stats_contribution(schedule: &VestingSchedule) -> Result<(i128, i128, i128), Error> {
+    let released = if schedule.status == GrantStatus::Cancelled {
+        vested_amount(schedule, u64::MAX)?
+    } else {
+        schedule.claimed
+    };
+    let owed = if schedule.status == GrantStatus::Active {
+        schedule.total_amount
+    } else {
+        released
+    };
+    let escrowed = owed
+        .checked_sub(schedule.claimed)
+        .ok_or(Error::MathOverflow)?;
+    Ok((escrowed, released, schedule.claimed))
+}
+
+/// Applies the difference between the previous and new grant state to the token aggregates.
+fn update_token_stats(env: &Env, previous: Option<&Grant>, grant: &Grant) -> Result<(), Error> {
+    let old = match previous {
+        Some(p) => stats_contribution(p)?,
+        None => (0, 0, 0),
+    };
+    apply_token_stats(env, old, stats_contribution(grant)?)
+}
+
+/// Applies the difference between the previous and new schedule to the token aggregates.
+fn update_vesting_token_stats(
+    env: &Env,
+    previous: Option<&VestingSchedule>,
+    schedule: &VestingSchedule,
+) -> Result<(), Error> {
+    let old = match previous {
+        Some(p) => vesting_stats_contribution(p)?,
+        None => (0, 0, 0),
+    };
+    apply_token_stats(env, old, vesting_stats_contribution(schedule)?)
+}
+
+fn apply_token_stats(
+    env: &Env,
+    (old_escrowed, old_released, old_withdrawn): (i128, i128, i128),
+    (new_escrowed, new_released, new_withdrawn): (i128, i128, i128),
+) -> Result<(), Error> {
+    let key = DataKey::TokenStats(read_grant_token(env)?);
+    let mut stats: TokenStats = read_record(env, &key).unwrap_or_default();
+    stats.escrowed = stats
+        .escrowed
+        .checked_add(new_escrowed - old_escrowed)
+        .ok_or(Error::MathOverflow)?;
+    stats.released = stats
+        .released
+        .checked_add(new_released - old_released)
+        .ok_or(Error::MathOverflow)?;
+    stats.withdrawn = stats
+        .withdrawn
+        .checked_add(new_withdrawn - old_withdrawn)
+        .ok_or(Error::MathOverflow)?;
+    write_record(env, &key, &stats);
+    Ok(())
+}
+
+/// Whether `grant_id` is used by either a streaming or a vesting grant.
+fn grant_id_taken(env: &Env, grant_id: u64) -> bool {
+    has_record(env, &DataKey::Grant(grant_id))
+        || has_record(env, &DataKey::Vesting(grant_id))
+        || has_record(env, &DataKey::Tombstone(grant_id))
+}
+
+fn is_archived(env: &Env, grant_id: u64) -> bool {
+    has_record(env, &DataKey::Archived(grant_id))
+}
+
+fn read_vesting(env: &Env, grant_id: u64) -> Option<VestingSchedule> {
+    read_record(env, &DataKey::Vesting(grant_id))
+}
+
+/// Persists a vesting schedule and applies the change to the token aggregates.
+fn write_vesting(env: &Env, grant_id: u64, schedule: &VestingSchedule) -> Result<(), Error> {
+    let key = DataKey::Vesting(grant_id);
+    let previous: Option<VestingSchedule> = read_record(env, &key);
+    write_record(env, &key, schedule);
+    update_vesting_token_stats(env, previous.as_ref(), schedule)
+}
+
+fn read_max_start_offset(env: &Env) -> Option<u64> {
+    env.storage()
+        .instance()
+        .get(&DataKey::MaxStartOffset)
+        .unwrap_or(Some(DEFAULT_MAX_START_OFFSET))
+}
+
+/// The vesting grant, if it is active and has not started yet, so nothing has vested under
+/// any curve or rounding.
+fn read_unstarted_vesting(env: &Env, grant_id: u64) -> Result<VestingSchedule, Error> {
+    let schedule = read_vesting(env, grant_id).ok_or_else(|| missing_grant_error(env, grant_id))?;
+    if schedule.status != GrantStatus::Active || vesting_now(env, &schedule) > schedule.start {
+        return Err(Error::InvalidState);
+    }
+    Ok(schedule)
+}
+
+/// Current time on the schedule's own clock.
+fn vesting_now(env: &Env, schedule: &VestingSchedule) -> u64 {
+    match schedule.time_basis {
+        TimeBasis::Timestamp => env.ledger().timestamp(),
+        TimeBasis::LedgerSequence => u64::from(env.ledger().sequence()),
+    }
+}
+
+/// Validates `tranches` and stores them as a new vesting grant spanning all of them.
+fn store_new_tranched_vesting(
+    env: &Env,
+    admin: &Address,
+    grant_id: u64,
+    recipient: Address,
+    total_amount: i128,
+    tranches: Vec<VestingTranche>,
+) -> Result<(), Error> {
+    if tranches.is_empty() {
+        return Err(Error::InvalidAmount);
+    }
+    if tranches.len() > MAX_TRANCHES {
+        return Err(Error::TooManyTranches);
+    }
+
+    let mut sum: i128 = 0;
+    let mut start = u64::MAX;
+    let mut end: u64 = 0;
+    let mut unlock = u64::MAX;
+    for tranche in tranches.iter() {
+        if tranche.amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        sum = sum.checked_add(tranche.amount).ok_or(Error::MathOverflow)?;
+        let tranche_end = tranche
+            .start
+            .checked_add(tranche.duration)
+            .ok_or(Error::MathOverflow)?;
+        let tranche_unlock = tranche
+            .start
+            .checked_add(tranche.cliff)
+            .ok_or(Error::MathOverflow)?;
+        start = start.min(tranche.start);
+        end = end.max(tranche_end);
+        unlock = unlock.min(tranche_unlock);
+    }
+    if sum != total_amount {
+        return Err(Error::InvalidAmount);
+    }
+
+    let schedule = VestingSchedule {
+        recipient,
+        total_amount,
+        start,
+        duration: end - start,
+        cliff: unlock - start,
+        claimed: 0,
+        status: GrantStatus::Active,
+        stopped_at: None,
+        total_paused_seconds: 0,
+        paused_at: None,
+        curve: VestingCurveData::Linear,
+        rounding: RoundingData::Floor,
+        time_basis: TimeBasis::Timestamp,
+        tranches,
+    };
+    store_new_vesting(env, admin, grant_id, schedule)
+}
+
+/// Maps a rejected segmented schedule to the error create_grant_vested_segments reports.
+fn schedule_error(error: vesting::ScheduleError) -> Error {
+    match error {
+        vesting::ScheduleError::TooManySegments => Error::TooManyTranches,
+        vesting::ScheduleError::MathOverflow => Error::MathOverflow,
+        _ => Error::InvalidAmount,
+    }
+}
+
+/// Validates and stores a new vesting grant; `schedule` fixes its time basis for good.
+fn store_new_vesting(
+    env: &Env,
+    admin: &Address,
+    grant_id: u64,
+    schedule: VestingSchedule,
+) -> Result<(), Error> {
+    ensure_not_paused(env, "create_grant_vested")?;
+    if schedule.total_amount <= 0 {
+        return Err(Error::InvalidAmount);
+    }
+    if grant_id_taken(env, grant_id) {
+        return Err(Error::GrantAlreadyExists);
+    }
+    check_self_grant(env, admin, &schedule.recipient)?;
+    check_grant_token(env)?;
+    check_min_funding(env, schedule.total_amount)?;
+    // Reject schedules whose cliff or end lies beyond the representable timeline up front,
+    // rather than failing on every later claim.
+    vested_amount(&schedule, schedule.start)?;
+    check_start_offset(env, &schedule)?;
+
+    write_vesting(env, grant_id, &schedule)?;
+    register_new_grant(
+        env,
+        admin,
+        grant_id,
+        schedule.recipient,
+        schedule.total_amount,
+    );
+    Ok(())
+}
+
+/// Fails with StartOutOfRange if a timestamp-based schedule, or any of its tranches, starts
+/// further from now than the configured MaxStartOffset.
+fn check_start_offset(env: &Env, schedule: &VestingSchedule) -> Result<(), Error> {
+    if schedule.time_basis == TimeBasis::Timestamp {
+        if let Some(max_offset) = read_max_start_offset(env) {
+            let now = env.ledger().timestamp();
+            let out_of_range = |start: u64| start.abs_diff(now) > max_offset;
+            if out_of_range(schedule.start)
+                || schedule.tranches.iter().any(|t| out_of_range(t.start))
+            {
+                return Err(Error::StartOutOfRange);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Seconds after `start` the schedule has been paused as of `now`, including a pause still in
+/// progress.
+fn paused_seconds(schedule: &VestingSchedule, now: u64) -> u64 {
+    match schedule.paused_at {
+        Some(paused_at) => schedule
+            .total_paused_seconds
+            .saturating_add(now.saturating_sub(paused_at.max(schedule.start))),
+        None => schedule.total_paused_seconds,
+    }
+}
+
+/// Ends a pause in progress, folding its length into `total_paused_seconds`.
+fn end_pause(schedule: &mut VestingSchedule, now: u64) {
+    schedule.total_paused_seconds = paused_seconds(schedule, now);
+    schedule.paused_at = None;
+}
+
+/// Amount of the schedule vested at `now`, or at cancellation if that came first, summed over
+/// its tranches if it has any. Time spent paused does not count towards any cliff or duration.
+fn vested_amount(schedule: &VestingSchedule, now: u64) -> Result<i128, Error> {
+    let now = match schedule.stopped_at {
+        Some(stopped_at) => now.min(stopped_at),
+        None => now,
+    };
+    let now = vesting::active_time(schedule.start, now, paused_seconds(schedule, now));
+    if schedule.tranches.is_empty() {
+        return tranche_vested(
+            schedule,
+            schedule.total_amount,
+            schedule.start,
+            schedule.cliff,
+            schedule.duration,
+            now,
+        );
+    }
+    let mut vested: i128 = 0;
+    for tranche in schedule.tranches.iter() {
+        let part = tranche_vested(
+            schedule,
+            tranche.amount,
+            tranche.start,
+            tranche.cliff,
+            tranche.duration,
+            now,
+        )?;
+        vested = vested.checked_add(part).ok_or(Error::MathOverflow)?;
+    }
+    Ok(vested)
+}
+
+/// Vested part of `amount` at active time `now`, on the schedule's curve and rounding.
+fn tranche_vested(
+    schedule: &VestingSchedule,
+    amount: i128,
+    start: u64,
+    cliff: u64,
+    duration: u64,
+    now: u64,
+) -> Result<i128, Error> {
+    // Amounts are validated positive at creation, so both conversions are lossless.
+    vesting::try_compute_claimable_curve_with_cliff(
+        amount as u128,
+        start,
+        cliff,
+        now,
+        duration,
+        schedule.curve.into(),
+        schedule.rounding.into(),
+    )
+    .map(|vested| vested as i128)
+    .map_err(|_| Error::MathOverflow)
+}
+
+/// Stops a vesting grant at the current time, fixing what has vested so far as the
+/// recipient's entitlement, and returns the unvested remainder for the caller to refund.
+fn stop_vesting(
+    env: &Env,
+    admin: Address,
+    grant_id: u64,
+    mut schedule: VestingSchedule,
+    action: HistoryAction,
+) -> Result<i128, Error> {
+    if schedule.status != GrantStatus::Active {
+        return Err(Error::InvalidState);
+    }
+
+    let now = vesting_now(env, &schedule);
+    let unvested = schedule
+        .total_amount
+        .checked_sub(vested_amount(&schedule, now)?)
+        .ok_or(Error::MathOverflow)?;
+    end_pause(&mut schedule, now);
+    schedule.stopped_at = Some(now);
+    schedule.status = GrantStatus::Cancelled;
+    write_vesting(env, grant_id, &schedule)?;
+    on_grant_cancelled(env, grant_id)?;
+    record_history(env, grant_id, action, admin, None);
+    Ok(unvested)
+}
+
+/// Stops a vesting grant at the current time and records its unvested remainder as owed to
+/// the treasury.
+fn cancel_vesting(
+    env: &Env,
+    admin: Address,
+    grant_id: u64,
+    schedule: VestingSchedule,
+) -> Result<(), Error> {
+    let unvested = stop_vesting(env, admin, grant_id, schedule, HistoryAction::Cancelled)?;
+    owe_refund(env, grant_id, unvested)
+}
+
+fn read_grant_token(env: &Env) -> Result<Address, Error> {
+    env.storage()
+        .instance()
+        .get(&DataKey::GrantToken)
+        .ok_or(Error::NotInitialized)
+}
+
+/// Fails with InvalidToken unless the grant token answers decimals(), so a mistyped token
+/// address is caught when the first grant is created rather than at the first payout. The
+/// answer is cached, so only the first creation pays for the call.
+fn check_grant_token(env: &Env) -> Result<(), Error> {
+    let instance = env.storage().instance();
+    if instance.has(&DataKey::TokenDecimals)
+        || instance.get(&DataKey::SkipTokenValidation).unwrap_or(false)
+    {
+        return Ok(());
+    }
+    let decimals = env.try_invoke_contract::<u32, InvokeError>(
+        &read_grant_token(env)?,
+        &Symbol::new(env, "decimals"),
+        Vec::new(env),
+    );
+    match decimals {
+        Ok(Ok(decimals)) => {
+            instance.set(&DataKey::TokenDecimals, &decimals);
+            Ok(())
+        }
+        _ => Err(Error::InvalidToken),
+    }
+}
+
+fn read_treasury(env: &Env) -> Result<Address, Error> {
+    env.storage()
+        .instance()
+        .get(&DataKey::Treasury)
+        .ok_or(Error::NotInitialized)
+}
+
+fn read_grant_ids(env: &Env) -> Vec<u64> {
+    read_record(env, &DataKey::GrantIds).unwrap_or_else(|| Vec::new(env))
+}
+
+fn read_index(env: &Env, key: &DataKey) -> Vec<u64> {
+    read_record(env, key).unwrap_or_else(|| Vec::new(env))
+}
+
+fn index_add(env: &Env, key: &DataKey, grant_id: u64) {
+    let mut ids = read_index(env, key);
+    ids.push_back(grant_id);
+    write_record(env, key, &ids);
+}
+
+fn index_remove(env: &Env, key: &DataKey, grant_id: u64) {
+    let mut ids = read_index(env, key);
+    if let Some(pos) = ids.first_index_of(grant_id) {
+        ids.remove(pos);
+        write_record(env, key, &ids);
+    }
+}
+
+/// Returns at most `limit` items starting at `start`, with `limit` capped at MAX_PAGE_SIZE.
+fn paginate<T>(env: &Env, items: &Vec<T>, start: u32, limit: u32) -> Vec<T>
+where
+    T: IntoVal<Env, Val> + TryFromVal<Env, Val>,
+{
+    let mut page = Vec::new(env);
+    let end = start
+        .saturating_add(limit.min(MAX_PAGE_SIZE))
+        .min(items.len());
+    for i in start..end {
+        page.push_back(items.get(i).unwrap());
+    }
+    page
+}
+
+fn prune_cancelled(env: &Env) -> bool {
+    env.storage()
+        .instance()
+        .get(&DataKey::PruneCancelled)
+        .unwrap_or(false)
+}
+
+/// Drops a freshly cancelled grant from the admin index if pruning is enabled.
+fn on_grant_cancelled(env: &Env, grant_id: u64) -> Result<(), Error> {
+    let admin = read_admin(env)?;
+    if prune_cancelled(env) {
+        index_remove(env, &DataKey::AdminGrants(admin.clone()), grant_id);
+    }
+    // A cancelled grant can never complete, so its bonus goes back to the admin who funded it.
+    release_bonus(env, grant_id, &admin)?;
+    Ok(())
+}
+
+/// Transfers the grant's unpaid bonus, if any, to `to` and forgets it so it moves only once.
+/// Returns the bonus that moved.
+fn release_bonus(env: &Env, grant_id: u64, to: &Address) -> Result<Option<GrantBonus>, Error> {
+    let key = DataKey::GrantBonus(grant_id);
+    let Some(bonus) = read_record::<GrantBonus>(env, &key) else {
+        return Ok(None);
+    };
+    remove_record(env, &key);
+    add_to_total(
+        env,
+        &DataKey::PendingBonuses(bonus.token.clone()),
+        -bonus.amount,
+    )?;
+    pay_token_from_contract(env, &bonus.token, to, bonus.amount)?;
+    Ok(Some(bonus))
+}
+
+/// Adds `amount` of `token` to what the grant has released to its recipient.
+fn record_release(env: &Env, grant_id: u64, token: &Address, amount: i128) -> Result<(), Error> {
+    let key = DataKey::ReleasedByToken(grant_id);
+    let mut released: Map<Address, i128> = read_record(env, &key).unwrap_or_else(|| Map::new(env));
+    let total = released
+        .get(token.clone())
+        .unwrap_or(0)
+        .checked_add(amount)
+        .ok_or(Error::MathOverflow)?;
+    released.set(token.clone(), total);
+    write_record(env, &key, &released);
+    Ok(())
+}
+
+/// A running total kept alongside the records it sums, so reading it does not depend on the
+/// number of grants.
+fn read_total(env: &Env, key: &DataKey) -> i128 {
+    read_record(env, key).unwrap_or(0)
+}
+
+fn add_to_total(env: &Env, key: &DataKey, delta: i128) -> Result<(), Error> {
+    let total = read_total(env, key)
+        .checked_add(delta)
+        .ok_or(Error::MathOverflow)?;
+    write_record(env, key, &total);
+    Ok(())
+}
+
+fn read_history(env: &Env, grant_id: u64) -> Vec<HistoryEntry> {
+    read_record(env, &DataKey::History(grant_id)).unwrap_or_else(|| Vec::new(env))
+}
+
+fn read_history_limit(env: &Env) -> u32 {
+    env.storage()
+        .instance()
+        .get(&DataKey::HistoryLimit)
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+}
+
+/// Appends to the grant's history, dropping the oldest entries beyond the configured limit.
+fn record_history(
+    env: &Env,
+    grant_id: u64,
+    action: HistoryAction,
+    actor: Address,
+    amount: Option<i128>,
+) {
+    let limit = read_history_limit(env);
+    let mut history = read_history(env, grant_id);
+    history.push_back(HistoryEntry {
+        action,
+        actor,
+        timestamp: env.ledger().timestamp(),
+        amount,
+    });
+    while history.len() > limit {
+        history.pop_front();
+    }
+    write_record(env, &DataKey::History(grant_id), &history);
+}
+
+/// Calls `on_withdraw(grant_id, amount)` on the grant's hook, if any. Failures are ignored so a
+/// broken hook can never block the recipient's withdrawal.
+fn notify_hook(env: &Env, grant_id: u64, amount: i128) {
+    let hook: Option<Address> = read_record(env, &DataKey::GrantHook(grant_id));
+    if let Some(hook) = hook {
+        let args: Vec<Val> = (grant_id, amount).into_val(env);
+        let _ = env.try_invoke_contract::<Val, soroban_sdk::Error>(
+            &hook,
+            &Symbol::new(env, "on_withdraw"),
+            args,
+        );
+    }
+}
+
+/// Fails with InsufficientFunding if the contract's grant token balance, less what existing
+/// grants are still owed (allocated_funds), is below min_funding_bps of `total_amount`.
+/// Runs before the new grant is stored, so its own amount is not yet counted as allocated.
+fn check_min_funding(env: &Env, total_amount: i128) -> Result<(), Error> {
+    let min_funding_bps: u32 = env
+        .storage()
+        .instance()
+        .get(&DataKey::MinFundingBps)
+        .unwrap_or(0);
+    if min_funding_bps == 0 {
+        return Ok(());
+    }
+    // total_amount is positive by the time grants are checked, so the casts are lossless.
+    let required = vesting::bps_of(total_amount as u128, min_funding_bps) as i128;
+    let balance =
+        token::Client::new(env, &read_grant_token(env)?).balance(&env.current_contract_address());
+    let unallocated = balance
+        .checked_sub(allocated_funds(env)?)
+        .ok_or(Error::MathOverflow)?;
+    if unallocated < required {
+        return Err(Error::InsufficientFunding);
+    }
+    Ok(())
+}
+
+/// What a token transfer did, measured from balances on both sides rather than trusted from
+/// the token.
+#[derive(Clone, Copy, Debug)]
+struct TransferOutcome {
+    requested: i128,
+    /// Amount that left the sender.
+    sent: i128,
+    /// Amount that reached the recipient; less than `sent` for a fee-on-transfer token.
+    received: i128,
+}
+
+/// Sends `amount` of the grant token from the contract to `to`, failing cleanly with
+/// InsufficientFunding rather than trapping in the token when the contract is underfunded.
+/// Fails with TransferFailed if the token's balances do not add up: the contract is not
+/// debited exactly `amount`, the recipient gains more than was sent, or a nonzero transfer
+/// delivers nothing. A fee short of that is reported in the outcome for the caller to judge.
+fn pay_from_contract(env: &Env, to: &Address, amount: i128) -> Result<TransferOutcome, Error> {
+    pay_token_from_contract(env, &read_grant_token(env)?, to, amount)
+}
+
+/// pay_from_contract for a token other than the grant token, such as a bonus token.
+fn pay_token_from_contract(
+    env: &Env,
+    token: &Address,
+    to: &Address,
+    amount: i128,
+) -> Result<TransferOutcome, Error> {
+    let contract = env.current_contract_address();
+    let client = token::Client::new(env, token);
+    let sender_before = client.balance(&contract);
+    if sender_before < amount {
+        return Err(Error::InsufficientFunding);
+    }
+    let recipient_before = client.balance(to);
+    client.transfer(&contract, to, &amount);
+
+    let sent = sender_before
+        .checked_sub(client.balance(&contract))
+        .ok_or(Error::MathOverflow)?;
+    let received = client
+        .balance(to)
+        .checked_sub(recipient_before)
+        .ok_or(Error::MathOverflow)?;
+    if sent != amount || received < 0 || received > sent || (amount > 0 && received == 0) {
+        return Err(Error::TransferFailed);
+    }
+    Ok(TransferOutcome {
+        requested: amount,
+        sent,
+        received,
+    })
+}
+
+/// Sends `amount` of the grant token from the contract to the treasury. No-op for zero.
+fn refund_to_treasury(env: &Env, amount: i128) -> Result<(), Error> {
+    if amount > 0 {
+        pay_from_contract(env, &read_treasury(env)?, amount)?;
+    }
+    Ok(())
+}
+
+/// Records `amount` as owed to the treasury; claim_refund moves it later, so a failing
+/// transfer cannot block the cancellation itself.
+fn owe_refund(env: &Env, grant_id: u64, amount: i128) -> Result<(), Error> {
+    if amount > 0 {
+        write_record(env, &DataKey::RefundOwed(grant_id), &amount);
+        add_to_total(env, &DataKey::RefundsOwed, amount)?;
+    }
+    Ok(())
+}
+
+/// Like owe_refund, but claim_refund pays `to` instead of the treasury.
+fn owe_refund_to(env: &Env, grant_id: u64, amount: i128, to: &Address) -> Result<(), Error> {
+    owe_refund(env, grant_id, amount)?;
+    if amount > 0 {
+        write_record(env, &DataKey::RefundTo(grant_id), to);
+    }
+    Ok(())
+}
+
+fn read_refund_owed(env: &Env, grant_id: u64) -> i128 {
+    read_record(env, &DataKey::RefundOwed(grant_id)).unwrap_or(0)
+}
+
+/// Grant tokens that must remain in the contract: what every grant is still owed (the grant
+/// token's escrowed stat) plus refunds not yet claimed. Both are running totals, so the cost
+/// does not grow with the number of grants. Fails with InvalidState while migrate_storage has
+/// not yet counted the grants of an older schema.
+fn allocated_funds(env: &Env) -> Result<i128, Error> {
+    if stored_schema_version(env) < SCHEMA_VERSION {
+        return Err(Error::InvalidState);
+    }
+    let stats: TokenStats =
+        read_record(env, &DataKey::TokenStats(read_grant_token(env)?)).unwrap_or_default();
+    stats
+        .escrowed
+        .checked_add(read_total(env, &DataKey::RefundsOwed))
+        .ok_or(Error::MathOverflow)
+}
+
+/// Storage layout the contract's data is in: the recorded SchemaVersion, 1 for a contract
+/// initialized before it was recorded, or this build's SCHEMA_VERSION if nothing is stored.
+fn stored_schema_version(env: &Env) -> u32 {
+    let instance = env.storage().instance();
+    match instance.get(&DataKey::SchemaVersion) {
+        Some(version) => version,
+        None if instance.has(&DataKey::Admin) => 1,
+        None => SCHEMA_VERSION,
+    }
+}
+
+/// What the contract must still hold for one grant, including a refund not yet claimed.
+fn outstanding_obligation(env: &Env, grant_id: u64) -> Result<i128, Error> {
+    let mut remaining = 0_i128;
+    if let Some(grant) = read_record::<Grant>(env, &DataKey::Grant(grant_id)) {
+        remaining = match grant.status {
+            // A completed grant still owes whatever the recipient has not withdrawn.
+            GrantStatus::Active | GrantStatus::Completed => grant
+                .total_amount
+                .checked_sub(grant.withdrawn)
+                .ok_or(Error::MathOverflow)?,
+            GrantStatus::Cancelled => grant.claimable,
+        };
+    } else if let Some(schedule) = read_vesting(env, grant_id) {
+        let owed = match schedule.status {
+            GrantStatus::Active => schedule.total_amount,
+            GrantStatus::Cancelled => vested_amount(&schedule, u64::MAX)?,
+            GrantStatus::Completed => schedule.claimed,
+        };
+        remaining = owed
+            .checked_sub(schedule.claimed)
+            .ok_or(Error::MathOverflow)?;
+    }
+    remaining
+        .checked_add(read_refund_owed(env, grant_id))
+        .ok_or(Error::MathOverflow)
+}
+
+fn read_dust_threshold(env: &Env) -> i128 {
+    env.storage()
+        .instance()
+        .get(&DataKey::DustThreshold)
+        .unwrap_or(0)
+}
+
+fn settle_grant(grant: &mut Grant, now: u64, dust_threshold: i128) -> Result<(), Error> {
+    if now < grant.last_update_ts {
+        return Err(Error::InvalidState);
+    }
+
+    let elapsed = now - grant.last_update_ts;
+    grant.last_update_ts = now;
+
+    if grant.status != GrantStatus::Active || elapsed == 0 || grant.flow_rate == 0 {
+        return Ok(());
+    }
+
+    if grant.flow_rate < 0 {
+        return Err(Error::InvalidRate);
+    }
+
+    let elapsed_i128 = i128::from(elapsed);
+    let accrued = grant
+        .flow_rate
+        .checked_mul(elapsed_i128)
+        .ok_or(Error::MathOverflow)?;
+
+    let accounted = grant
+        .withdrawn
+        .checked_add(grant.claimable)
+        .ok_or(Error::MathOverflow)?;
+
+    if accounted > grant.total_amount {
+        return Err(Error::InvalidState);
+    }
+
+    let remaining = grant
+        .total_amount
+        .checked_sub(accounted)
+        .ok_or(Error::MathOverflow)?;
+
+    let delta = if accrued > remaining {
+        remaining
+    } else {
+        accrued
+    };
+
+    grant.claimable = grant
+        .claimable
+        .checked_add(delta)
+        .ok_or(Error::MathOverflow)?;
+
+    let new_accounted = grant
+        .withdrawn
+        .checked_add(grant.claimable)
+        .ok_or(Error::MathOverflow)?;
+
+    let shortfall = grant
+        .total_amount
+        .checked_sub(new_accounted)
+        .ok_or(Error::MathOverflow)?;
+
+    if shortfall <= dust_threshold {
+        grant.status = GrantStatus::Completed;
+    }
+
+    Ok(())
+}
+
+/// Settles the grant at `now` and moves `amount` from claimable to withdrawn.
+fn apply_withdrawal(
+    grant: &mut Grant,
+    amount: i128,
+    now: u64,
+    dust_threshold: i128,
+) -> Result<(), Error> {
+    settle_grant(grant, now, dust_threshold)?;
+
+    if amount > grant.claimable {
+        return Err(Error::InsufficientReleasedBalance);
+    }
+
+    grant.claimable = grant
+        .claimable
+        .checked_sub(amount)
+        .ok_or(Error::MathOverflow)?;
+    grant.withdrawn = grant
+        .withdrawn
+        .checked_add(amount)
+        .ok_or(Error::MathOverflow)?;
+
+    let accounted = grant
+        .withdrawn
+        .checked_add(grant.claimable)
+        .ok_or(Error::MathOverflow)?;
+
+    if accounted > grant.total_amount {
+        return Err(Error::InvalidState);
+    }
+
+    if grant.withdrawn == grant.total_amount && grant.status == GrantStatus::Active {
+        grant.status = GrantStatus::Completed;
+    }
+
+    grant.last_claim_time = now;
+    Ok(())
+}
+
+/// Whether a stored grant's amounts and status agree with each other.
+fn grant_is_consistent(grant: &Grant, dust_threshold: i128) -> bool {
+    if grant.withdrawn < 0 || grant.claimable < 0 || grant.flow_rate < 0 {
+        return false;
+    }
+    if grant.rate_updated_at > grant.last_update_ts {
+        return false;
+    }
+    let released = match grant.withdrawn.checked_add(grant.claimable) {
+        Some(released) => released,
+        None => return false,
+    };
+    if released > grant.total_amount {
+        return false;
+    }
+    match grant.status {
+        GrantStatus::Active => released < grant.total_amount,
+        // A positive dust threshold lets a grant complete slightly short of its total.
+        GrantStatus::Completed => released == grant.total_amount || dust_threshold > 0,
+        GrantStatus::Cancelled => grant.flow_rate == 0,
+    }
+}
+
+/// Vesting counterpart of grant_is_consistent: claimed <= vested <= total, and the status
+/// matches the amounts.
+fn vesting_is_consistent(schedule: &VestingSchedule, now: u64) -> bool {
+    if schedule.total_amount < 0 || schedule.claimed < 0 {
+        return false;
+    }
+    let vested = match vested_amount(schedule, now) {
+        Ok(vested) => vested,
+        Err(_) => return false,
+    };
+    if schedule.claimed > vested || vested > schedule.total_amount {
+        return false;
+    }
+    match schedule.status {
+        GrantStatus::Active => {
+            schedule.stopped_at.is_none() && schedule.claimed < schedule.total_amount
+        }
+        GrantStatus::Completed => schedule.claimed == schedule.total_amount,
+        GrantStatus::Cancelled => schedule.stopped_at.is_some(),
+    }
+}
+
+fn read_amount_scale(env: &Env, grant_id: u64) -> u32 {
+    read_record(env, &DataKey::AmountScale(grant_id)).unwrap_or(0)
+}
+
+/// `amount` as (amount / 10^scale, amount % 10^scale). `scale` is at most MAX_AMOUNT_SCALE,
+/// so the divisor always fits in an i128.
+fn split_scaled(amount: i128, scale: u32) -> (i128, i128) {
+    let unit = 10_i128.pow(scale);
+    (amount / unit, amount % unit)
+}
+
+/// `part * 10_000 / whole`, rounded down. Returns 0 for a non-positive `whole`.
+fn ratio_bps(part: i128, whole: i128) -> u32 {
+    if whole <= 0 || part <= 0 {
+        return 0;
+    }
+    let bps = match part.checked_mul(10_000) {
+        Some(scaled) => scaled / whole,
+        // Only reachable for amounts near i128::MAX, where whole / 10_000 loses under one bps.
+        None => part / (whole / 10_000),
+    };
+    bps.min(10_000) as u32
+}
+
+fn store_new_grant(
+    env: &Env,
+    admin: &Address,
+    grant_id: u64,
+    recipient: Address,
+    total_amount: i128,
+    flow_rate: i128,
+) -> Result<(), Error> {
+    ensure_not_paused(env, "create_grant")?;
+    if total_amount <= 0 {
+        return Err(Error::InvalidAmount);
+    }
+
+    if flow_rate < 0 {
+        return Err(Error::InvalidRate);
+    }
+
+    if grant_id_taken(env, grant_id) {
+        return Err(Error::GrantAlreadyExists);
+    }
+    check_self_grant(env, admin, &recipient)?;
+    check_grant_token(env)?;
+    check_min_funding(env, total_amount)?;
+
+    let now = env.ledger().timestamp();
+    let grant = Grant {
+        recipient: recipient.clone(),
+        total_amount,
+        withdrawn: 0,
+        claimable: 0,
+        flow_rate,
+        last_update_ts: now,
+        rate_updated_at: now,
+        last_claim_time: now,
+        status: GrantStatus::Active,
+    };
+
+    write_grant(env, grant_id, &grant)?;
+    register_new_grant(env, admin, grant_id, recipient, total_amount);
+    Ok(())
+}
+
+/// Bookkeeping shared by every kind of grant once its state is stored: the id list, admin and
+/// grantee indexes, creation time and the Created history entry.
+fn register_new_grant(
+    env: &Env,
+    admin: &Address,
+    grant_id: u64,
+    recipient: Address,
+    total_amount: i128,
+) {
+    let mut ids = read_grant_ids(env);
+    ids.push_back(grant_id);
+    write_record(env, &DataKey::GrantIds, &ids);
+    index_add(env, &DataKey::AdminGrants(admin.clone()), grant_id);
+    index_add(env, &DataKey::GranteeGrants(recipient), grant_id);
+    write_record(
+        env,
+        &DataKey::CreatedAt(grant_id),
+        &env.ledger().timestamp(),
+    );
+    record_history(
+        env,
+        grant_id,
+        HistoryAction::Created,
+        admin.clone(),
+        Some(total_amount),
+    );
+}
+
+/// Counts `amount` against the grant's withdrawal limit, if it has one, and returns the
+/// updated window. The window restarts once `period` seconds have passed since it opened.
+fn charge_withdraw_limit(
+    env: &Env,
+    grant_id: u64,
+    amount: i128,
+    now: u64,
+) -> Result<Option<WithdrawWindow>, Error> {
+    let key = DataKey::WithdrawLimit(grant_id);
+    let limit: WithdrawLimit = match read_record(env, &key) {
+        Some(limit) => limit,
+        None => return Ok(None),
+    };
+
+    let mut window: WithdrawWindow = read_record(env, &DataKey::WithdrawWindow(grant_id))
+        .unwrap_or(WithdrawWindow {
+            start: now,
+            withdrawn: 0,
+        });
+    if now.saturating_sub(window.start) >= limit.period {
+        window = WithdrawWindow {
+            start: now,
+            withdrawn: 0,
+        };
+    }
+
+    window.withdrawn = window
+        .withdrawn
+        .checked_add(amount)
+        .ok_or(Error::MathOverflow)?;
+    if window.withdrawn > limit.max_per_period {
+        return Err(Error::RateLimited);
+    }
+    Ok(Some(window))
+}
+
+/// Points the grant at `new_recipient` and moves it between the grantee indexes.
+fn reassign_recipient(
+    env: &Env,
+    grant_id: u64,
+    mut grant: Grant,
+    new_recipient: Address,
+    actor: Address,
+) -> Result<(), Error> {
+    let old_recipient = grant.recipient.clone();
+    if old_recipient == new_recipient {
+        return Ok(());
+    }
+    check_self_grant(env, &read_admin(env)?, &new_recipient)?;
+
+    grant.recipient = new_recipient.clone();
+    write_grant(env, grant_id, &grant)?;
+    index_remove(env, &DataKey::GranteeGrants(old_recipient), grant_id);
+    index_add(env, &DataKey::GranteeGrants(new_recipient), grant_id);
+    record_history(env, grant_id, HistoryAction::RecipientChanged, actor, None);
+    Ok(())
+}
+
+/// Address that must authorize withdrawals: the admin for payroll-style grants, otherwise
+/// the recipient.
+fn withdraw_authorizer(env: &Env, grant_id: u64, grant: &Grant) -> Result<Address, Error> {
+    let admin_withdraws: bool =
+        read_record(env, &DataKey::AdminWithdraws(grant_id)).unwrap_or(false);
+    if admin_withdraws {
+        read_admin(env)
+    } else {
+        Ok(grant.recipient.clone())
+    }
+}
+
+/// Moves `amount` of an already authorized grant from claimable to withdrawn, records it and
+/// transfers it to the recipient, measuring what arrived.
+fn withdraw_from(
+    env: &Env,
+    grant_id: u64,
+    mut grant: Grant,
+    amount: i128,
+    actor: Address,
+) -> Result<WithdrawReceipt, Error> {
+    ensure_not_paused(env, "withdraw")?;
+    let now = env.ledger().timestamp();
+    let applied = apply_withdrawal(&mut grant, amount, now, read_dust_threshold(env));
+    if applied == Err(Error::InsufficientReleasedBalance) {
+        // Failed calls publish no contract events, so leave the figures in the diagnostics.
+        log!(
+            env,
+            "withdraw exceeds released balance: requested, available",
+            amount,
+            grant.claimable
+        );
+    }
+    applied?;
+    if let Some(window) = charge_withdraw_limit(env, grant_id, amount, now)? {
+        write_record(env, &DataKey::WithdrawWindow(grant_id), &window);
+    }
+    write_grant(env, grant_id, &grant)?;
+    record_history(env, grant_id, HistoryAction::Withdrawn, actor, Some(amount));
+    record_release(env, grant_id, &read_grant_token(env)?, amount)?;
+    let outcome = pay_from_contract(env, &grant.recipient, amount)?;
+    if grant.status == GrantStatus::Completed {
+        if let Some(bonus) = release_bonus(env, grant_id, &grant.recipient)? {
+            record_release(env, grant_id, &bonus.token, bonus.amount)?;
+        }
+    }
+    notify_hook(env, grant_id, amount);
+    Ok(WithdrawReceipt {
+        requested: outcome.requested,
+        transferred: outcome.received,
+        new_released: grant.withdrawn,
+        timestamp: now,
+    })
+}
+
+/// Runs the withdrawal validation and accounting against a copy of the stored grant.
+fn dry_run_withdraw(env: &Env, grant_id: u64, amount: i128) -> Result<Grant, Error> {
+    if amount <= 0 {
+        return Err(Error::InvalidAmount);
+    }
+
+    let mut grant = read_grant(env, grant_id)?;
+    let now = env.ledger().timestamp();
+    apply_withdrawal(&mut grant, amount, now, read_dust_threshold(env))?;
+    charge_withdraw_limit(env, grant_id, amount, now)?;
+    let balance =
+        token::Client::new(env, &read_grant_token(env)?).balance(&env.current_contract_address());
+    if balance < amount {
+        return Err(Error::InsufficientFunding);
+    }
+    Ok(grant)
+}
+
+/// Stops an active grant, leaving its accrued balance claimable and recording the rest as
+/// owed to the treasury.
+fn cancel(env: &Env, admin: Address, grant_id: u64, mut grant: Grant) -> Result<(), Error> {
+    if grant.status != GrantStatus::Active {
+        return Err(Error::InvalidState);
+    }
+
+    settle_grant(
+        &mut grant,
+        env.ledger().timestamp(),
+        read_dust_threshold(env),
+    )?;
+    let unvested = grant
+        .total_amount
+        .checked_sub(grant.withdrawn)
+        .and_then(|owed| owed.checked_sub(grant.claimable))
+        .ok_or(Error::MathOverflow)?;
+
+    grant.flow_rate = 0;
+    grant.status = GrantStatus::Cancelled;
+    write_grant(env, grant_id, &grant)?;
+    on_grant_cancelled(env, grant_id)?;
+    record_history(env, grant_id, HistoryAction::Cancelled, admin, None);
+
+    owe_refund(env, grant_id, unvested)
+}
+
+/// What claim_vested would pay out for the schedule right now.
+fn vested_claimable(env: &Env, schedule: &VestingSchedule) -> Result<i128, Error> {
+    // Both amounts are non-negative and at most total_amount, so the casts are lossless.
+    let vested = vested_amount(schedule, vesting_now(env, schedule))? as u128;
+    Ok(vesting::ClaimState::new(schedule.claimed as u128).claimable_of(vested) as i128)
+}
+
+/// Pays a vesting grant's recipient everything vested but unclaimed, measuring what arrived.
+fn settle_vested_claim(env: &Env, grant_id: u64) -> Result<WithdrawReceipt, Error> {
+    ensure_not_paused(env, "claim_vested")?;
+    let mut schedule =
+        read_vesting(env, grant_id).ok_or_else(|| missing_grant_error(env, grant_id))?;
+    schedule.recipient.require_auth();
+
+    // Both amounts are non-negative and at most total_amount, so the casts are lossless.
+    let vested = vested_amount(&schedule, vesting_now(env, &schedule))? as u128;
+    let mut claims = vesting::ClaimState::new(schedule.claimed as u128);
+    let amount = claims.claimable_of(vested);
+    claims
+        .record_claim(amount, vested)
+        .map_err(|_| Error::NothingToClaim)?;
+    let amount = amount as i128;
+
+    schedule.claimed = claims.claimed() as i128;
+    // A revoked or cancelled schedule stays Cancelled even once everything vested is claimed.
+    if schedule.status == GrantStatus::Active && schedule.claimed == schedule.total_amount {
+        schedule.status = GrantStatus::Completed;
+        on_grant_completed(
+            env,
+            grant_id,
+            &schedule.recipient,
+            schedule.total_amount,
+            schedule.claimed,
+        );
+    }
+    write_vesting(env, grant_id, &schedule)?;
+    record_history(
+        env,
+        grant_id,
+        HistoryAction::Withdrawn,
+        schedule.recipient.clone(),
+        Some(amount),
+    );
+
+    let outcome = pay_from_contract(env, &schedule.recipient, amount)?;
+    record_release(env, grant_id, &read_grant_token(env)?, amount)?;
+    if let Some(max_fee_bps) = read_record::<u32>(env, &DataKey::MaxFeeBps(grant_id)) {
+        // Returning an error reverts the transfer along with the rest of the claim.
+        let allowed_fee = vesting::bps_of(outcome.requested as u128, max_fee_bps) as i128;
+        if outcome.sent - outcome.received > allowed_fee {
+            return Err(Error::ExcessiveFee);
+        }
+    }
+    Ok(WithdrawReceipt {
+        requested: outcome.requested,
+        transferred: outcome.received,
+        new_released: schedule.claimed,
+        timestamp: env.ledger().timestamp(),
+    })
+}
+
+fn preview_grant_at_now(env: &Env, grant: &Grant) -> Result<Grant, Error> {
+    let mut preview = grant.clone();
+    settle_grant(&mut preview, env.ledger().timestamp(), read_dust_threshold(env))?;
+    Ok(preview)
+}
+
+#[contractimpl]
+impl GrantContract {
+    pub fn initialize(
+        env: Env,
+        admin: Address,
+        grant_token: Address,
+        treasury: Address,
+    ) -> Result<(), Error> {
+        if env.storage().instance().has(&DataKey::Admin) {
+            return Err(Error::AlreadyInitialized);
+        }
+        admin.require_auth();
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage().instance().set(&DataKey::GrantToken, &grant_token);
+        env.storage().instance().set(&DataKey::Treasury, &treasury);
+        write_record(&env, &DataKey::GrantIds, &Vec::<u64>::new(&env));
+        env.storage()
+            .instance()
+            .set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+        Ok(())
+    }
+
+    /// Admin-only, and only once. Sets the multisig that runs AdminActions: `threshold` of the
+    /// distinct `admins` (at most MAX_MULTISIG_ADMINS) must approve an action through
+    /// execute_admin_action. Later changes need the multisig's own approval of an
+    /// AdminAction::SetMultisig; calling this again fails with NotAuthorized.
+    pub fn set_admin_multisig(env: Env, admins: Vec<Address>, threshold: u32) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        if env.storage().instance().has(&DataKey::AdminMultisig) {
+            return Err(Error::NotAuthorized);
+        }
+        let multisig = AdminMultisig { admins, threshold };
+        validate_multisig(&multisig)?;
+        env.storage()
+            .instance()
+            .set(&DataKey::AdminMultisig, &multisig);
+        Ok(())
+    }
+
+    pub fn get_admin_multisig(env: Env) -> Option<AdminMultisig> {
+        env.storage().instance().get(&DataKey::AdminMultisig)
+    }
+
+    /// Multisig admin only. Records `approver`'s approval of `action` and, once `threshold`
+    /// current admins have approved it, runs it and clears its approvals. Returns whether the
+    /// action ran. Approving the same action twice counts once.
+    pub fn execute_admin_action(
+        env: Env,
+        approver: Address,
+        action: AdminAction,
+    ) -> Result<bool, Error> {
+        approver.require_auth();
+        let multisig: AdminMultisig = env
+            .storage()
+            .instance()
+            .get(&DataKey::AdminMultisig)
+            .ok_or(Error::NotAuthorized)?;
+        if !multisig.admins.contains(&approver) {
+            return Err(Error::NotAuthorized);
+        }
+        if let AdminAction::SetMultisig(new_multisig) = &action {
+            validate_multisig(new_multisig)?;
+        }
+
+        let key = DataKey::ActionApprovals(env.crypto().sha256(&action.clone().to_xdr(&env)));
+        let mut approvals: Vec<Address> = read_record(&env, &key).unwrap_or_else(|| Vec::new(&env));
+        if !approvals.contains(&approver) {
+            approvals.push_back(approver);
+        }
+        let approved = approvals
+            .iter()
+            .filter(|a| multisig.admins.contains(a))
+            .count() as u32;
+        if approved < multisig.threshold {
+            write_record(&env, &key, &approvals);
+            return Ok(false);
+        }
+
+        remove_record(&env, &key);
+        match action.clone() {
+            AdminAction::SetPaused(paused) => {
+                env.storage().instance().set(&DataKey::Paused, &paused);
+            }
+            AdminAction::SetPauseExemptions(functions) => {
+                env.storage()
+                    .instance()
+                    .set(&DataKey::PauseExemptions, &functions);
+            }
+            AdminAction::Upgrade(wasm_hash) => {
+                env.deployer().update_current_contract_wasm(wasm_hash);
+            }
+            AdminAction::SetMultisig(new_multisig) => {
+                env.storage()
+                    .instance()
+                    .set(&DataKey::AdminMultisig, &new_multisig);
+            }
+        }
+        env.events()
+            .publish((symbol_short!("admin_act"),), (action, approvals));
+        Ok(true)
+    }
+
+    /// Entrypoints that keep working while the contract is paused. Variants share their base
+    /// entrypoint's name: every create_grant_* streaming variant is create_grant, the vested
+    /// ones are create_grant_vested, and cancel_grant_idempotent is cancel_grant.
+    pub fn get_pause_exemptions(env: Env) -> Vec<Symbol> {
+        read_pause_exemptions(&env)
+    }
+
+    pub fn is_paused(env: Env) -> bool {
+        env.storage()
+            .instance()
+            .get(&DataKey::Paused)
+            .unwrap_or(false)
+    }
+
+    /// Semantic version of the deployed build as (major, minor, patch).
+    pub fn version(_env: Env) -> (u32, u32, u32) {
+        CONTRACT_VERSION
+    }
+
+    /// Storage layout version the contract's data is in: the one recorded at initialization
+    /// or by migrate_storage, 1 for a contract initialized before versions were recorded, or
+    /// this build's SCHEMA_VERSION if nothing has been written yet.
+    pub fn schema_version(env: Env) -> u32 {
+        stored_schema_version(&env)
+    }
+
+    /// Anyone may call, paying the rent. Extends the TTL of the grant's record, and of every
+    /// other record belonging to the grant, to `extend_to` ledgers, capped at the network
+    /// maximum, so a dormant grant is not archived. Returns the TTL applied. Records not yet
+    /// moved by migrate_storage live as long as the contract instance, so for those the
+    /// instance is extended instead.
+    pub fn bump_grant(env: Env, grant_id: u64, extend_to: u32) -> Result<u32, Error> {
+        let extend_to = extend_to.min(env.storage().max_ttl());
+        if !has_record(&env, &DataKey::Grant(grant_id))
+            && !has_record(&env, &DataKey::Vesting(grant_id))
+        {
+            return Err(Error::GrantNotFound);
+        }
+        for key in grant_record_keys(grant_id) {
+            if env.storage().persistent().has(&key) {
+                extend_record_ttl(&env, &key, extend_to, extend_to);
+            } else if env.storage().instance().has(&key) {
+                env.storage().instance().extend_ttl(extend_to, extend_to);
+            }
+        }
+        Ok(extend_to)
+    }
+
+    /// Anyone may call, paying the rent. Maintenance hook that extends the TTL of the contract
+    /// instance, which holds the configuration, and of the contract code.
+    pub fn bump_contract(env: Env) {
+        let extend_to = RECORD_TTL_EXTEND_TO.min(env.storage().max_ttl());
+        env.storage()
+            .instance()
+            .extend_ttl(RECORD_TTL_THRESHOLD.min(extend_to), extend_to);
+    }
+
+    /// Admin-only. Brings a contract deployed under schema 1 up to SCHEMA_VERSION in batches:
+    /// for up to `limit` grants (capped at MAX_PAGE_SIZE), starting at index `start` of the
+    /// creation-ordered id list, moves the grant from instance to persistent storage and adds
+    /// it to the admin, grantee and status indexes and the token stats, which schema 1 did not
+    /// keep. Returns how many ids remain after this batch; the batch that reaches the end also
+    /// moves the id list and records SCHEMA_VERSION. Grants already moved are skipped, so
+    /// batches may be repeated. Until its batch has run, a grant can be read but not changed.
+    pub fn migrate_storage(env: Env, start: u32, limit: u32) -> Result<u32, Error> {
+        let admin = require_admin_auth(&env)?;
+        let ids = read_grant_ids(&env);
+        let end = start.saturating_add(limit.min(MAX_PAGE_SIZE)).min(ids.len());
+        for i in start..end {
+            let grant_id = ids.get(i).unwrap();
+            let key = DataKey::Grant(grant_id);
+            let Some(grant) = env.storage().instance().get::<_, Grant>(&key) else {
+                continue;
+            };
+            write_record(&env, &key, &grant);
+            update_token_stats(&env, None, &grant)?;
+            index_add(&env, &DataKey::AdminGrants(admin.clone()), grant_id);
+            index_add(&env, &DataKey::GranteeGrants(grant.recipient), grant_id);
+            index_add(&env, &DataKey::GrantsByStatus(grant.status), grant_id);
+        }
+
+        if end == ids.len() {
+            migrate_record(&env, &DataKey::GrantIds);
+            env.storage()
+                .instance()
+                .set(&DataKey::SchemaVersion, &SCHEMA_VERSION);
+        }
+        Ok(ids.len() - end)
+    }
+
+    pub fn create_grant(
+        env: Env,
+        grant_id: u64,
+        recipient: Address,
+        total_amount: i128,
+        flow_rate: i128,
+    ) -> Result<(), Error> {
+        let admin = read_admin(&env)?;
+        admin.require_auth();
+        store_new_grant(&env, &admin, grant_id, recipient, total_amount, flow_rate)
+    }
+
+    /// Admin-only. Like create_grant, but also escrows `bonus_amount` of `bonus_token` from the
+    /// admin. The bonus is paid to the recipient when the grant completes, or refunded to the
+    /// admin if it is cancelled first.
+    pub fn create_grant_with_bonus(
+        env: Env,
+        grant_id: u64,
+        recipient: Address,
+        total_amount: i128,
+        flow_rate: i128,
+        bonus_amount: i128,
+        bonus_token: Address,
+    ) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        if bonus_amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        store_new_grant(&env, &admin, grant_id, recipient, total_amount, flow_rate)?;
+
+        token::Client::new(&env, &bonus_token).transfer(
+            &admin,
+            &env.current_contract_address(),
+            &bonus_amount,
+        );
+        add_to_total(
+            &env,
+            &DataKey::PendingBonuses(bonus_token.clone()),
+            bonus_amount,
+        )?;
+        let bonus = GrantBonus {
+            token: bonus_token,
+            amount: bonus_amount,
+        };
+        write_record(&env, &DataKey::GrantBonus(grant_id), &bonus);
+        Ok(())
+    }
+
+    /// Read-only. The grant's bonus while it is still escrowed; None once paid or refunded.
+    pub fn get_grant_bonus(env: Env, grant_id: u64) -> Option<GrantBonus> {
+        read_record(&env, &DataKey::GrantBonus(grant_id))
+    }
+
+    /// Admin-only. Like create_grant, but the contract assigns the next free numeric id and returns it.
+    pub fn create_grant_auto(
+        env: Env,
+        recipient: Address,
+        total_amount: i128,
+        flow_rate: i128,
+    ) -> Result<u64, Error> {
+        let admin = read_admin(&env)?;
+        admin.require_auth();
+
+        let mut grant_id: u64 = env
+            .storage()
+            .instance()
+            .get(&DataKey::NextGrantId)
+            .unwrap_or(1);
+        // Skip ids already taken by explicitly numbered grants.
+        while grant_id_taken(&env, grant_id) {
+            grant_id = grant_id.checked_add(1).ok_or(Error::MathOverflow)?;
+        }
+
+        store_new_grant(&env, &admin, grant_id, recipient, total_amount, flow_rate)?;
+        let next = grant_id.checked_add(1).ok_or(Error::MathOverflow)?;
+        env.storage().instance().set(&DataKey::NextGrantId, &next);
+        Ok(grant_id)
+    }
+
+    /// Admin-only. Creates a grant that vests `total_amount` linearly over `duration` seconds
+    /// from `start`, with nothing claimable until `start + cliff`. The recipient collects with
+    /// claim_vested, which transfers from the contract's grant token balance.
+    ///
+    /// `start` may be in the past, for work already performed, so whatever has vested by now
+    /// is claimable at once; or in the future, to delay the start. Either way it must be
+    /// within the maximum start offset of the current time (see set_max_start_offset).
+    pub fn create_grant_vested(
+        env: Env,
+        grant_id: u64,
+        recipient: Address,
+        total_amount: i128,
+        start: u64,
+        duration: u64,
+        cliff: u64,
+    ) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        let schedule = VestingSchedule {
+            recipient,
+            total_amount,
+            start,
+            duration,
+            cliff,
+            claimed: 0,
+            status: GrantStatus::Active,
+            stopped_at: None,
+            total_paused_seconds: 0,
+            paused_at: None,
+            curve: VestingCurveData::Linear,
+            rounding: RoundingData::Floor,
+            time_basis: TimeBasis::Timestamp,
+            tranches: Vec::new(&env),
+        };
+        store_new_vesting(&env, &admin, grant_id, schedule)
+    }
+
+    /// Admin-only. Creates a vesting grant made of up to MAX_TRANCHES tranches, each vesting
+    /// its own amount from its own start, with its own duration and cliff, e.g. 500 over a
+    /// year from now plus 500 over two years from six months out. Tranche amounts must be
+    /// positive and sum to `total_amount`. The tranches cannot be changed afterwards;
+    /// claim_vested pays the sum vested across all of them.
+    pub fn create_grant_vested_tranches(
+        env: Env,
+        grant_id: u64,
+        recipient: Address,
+        total_amount: i128,
+        tranches: Vec<VestingTranche>,
+    ) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        store_new_tranched_vesting(&env, &admin, grant_id, recipient, total_amount, tranches)
+    }
+
+    /// Admin-only. Like create_grant_vested_tranches, but takes the schedule in its segment
+    /// form: each segment of `schedule` becomes a tranche starting `start_offset` after
+    /// `start`, with no cliff, vesting its amount or basis point share of `total_amount`.
+    /// The schedule is validated as vesting::Schedule::new validates it; too many segments
+    /// fail with TooManyTranches and any other invalid schedule with InvalidAmount.
+    pub fn create_grant_vested_segments(
+        env: Env,
+        grant_id: u64,
+        recipient: Address,
+        total_amount: i128,
+        start: u64,
+        schedule: VestingScheduleData,
+    ) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        if total_amount <= 0 {
+            return Err(Error::InvalidAmount);
+        }
+        let segments = vesting::Schedule::try_from(&schedule).map_err(schedule_error)?;
+        // total_amount is positive, so the cast is lossless.
+        let amounts = segments
+            .segment_amounts(total_amount as u128)
+            .map_err(schedule_error)?;
+
+        let mut tranches = Vec::new(&env);
+        for (segment, amount) in segments.segments().iter().zip(amounts) {
+            // A zero basis point segment releases nothing, and tranches must be positive.
+            if amount == 0 {
+                continue;
+            }
+            tranches.push_back(VestingTranche {
+                // The amounts add up to total_amount, so each fits in an i128.
+                amount: amount as i128,
+                start: start
+                    .checked_add(segment.start_offset)
+                    .ok_or(Error::MathOverflow)?,
+                duration: segment.duration,
+                cliff: 0,
+            });
+        }
+        store_new_tranched_vesting(&env, &admin, grant_id, recipient, total_amount, tranches)
+    }
+
+    /// Admin-only. Like create_grant_vested, but `start` is a ledger sequence number and
+    /// `duration` and `cliff` count ledgers, so the schedule never depends on ledger
+    /// timestamps.
+    pub fn create_grant_vested_by_ledger(
+        env: Env,
+        grant_id: u64,
+        recipient: Address,
+        total_amount: i128,
+        start_ledger: u32,
+        duration_ledgers: u32,
+        cliff_ledgers: u32,
+    ) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        let schedule = VestingSchedule {
+            recipient,
+            total_amount,
+            start: u64::from(start_ledger),
+            duration: u64::from(duration_ledgers),
+            cliff: u64::from(cliff_ledgers),
+            claimed: 0,
+            status: GrantStatus::Active,
+            stopped_at: None,
+            total_paused_seconds: 0,
+            paused_at: None,
+            curve: VestingCurveData::Linear,
+            rounding: RoundingData::Floor,
+            time_basis: TimeBasis::LedgerSequence,
+            tranches: Vec::new(&env),
+        };
+        store_new_vesting(&env, &admin, grant_id, schedule)
+    }
+
+    /// Admin-only. Requires the contract to hold at least `min_funding_bps` of a grant's total
+    /// in the grant token, beyond what existing grants are still owed, before the grant can be
+    /// created, which is when grants go active.
+    /// 0, the default, turns the check off; 10_000 requires full funding up front.
+    pub fn set_min_funding_bps(env: Env, min_funding_bps: u32) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        vesting::validate_bps(min_funding_bps).map_err(|_| Error::InvalidAmount)?;
+        env.storage()
+            .instance()
+            .set(&DataKey::MinFundingBps, &min_funding_bps);
+        Ok(())
+    }
+
+    pub fn get_min_funding_bps(env: Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&DataKey::MinFundingBps)
+            .unwrap_or(0)
+    }
+
+    /// Read-only. Whether the contract's grant token balance covers everything still owed on
+    /// this grant: the unwithdrawn or unclaimed amount plus any refund not yet claimed. Other
+    /// grants' obligations are not counted against the balance.
+    pub fn is_funded(env: Env, grant_id: u64) -> Result<bool, Error> {
+        ensure_grant_exists(&env, grant_id)?;
+        let owed = outstanding_obligation(&env, grant_id)?;
+        let balance = token::Client::new(&env, &read_grant_token(&env)?)
+            .balance(&env.current_contract_address());
+        Ok(balance >= owed)
+    }
+
+    pub fn get_vesting_schedule(env: Env, grant_id: u64) -> Result<VestingSchedule, Error> {
+        read_vesting(&env, grant_id).ok_or_else(|| missing_grant_error(&env, grant_id))
+    }
+
+    /// Read-only. The vesting grant's tranches; a single-schedule grant reports one tranche
+    /// covering its whole amount.
+    pub fn get_vesting_tranches(env: Env, grant_id: u64) -> Result<Vec<VestingTranche>, Error> {
+        let schedule =
+            read_vesting(&env, grant_id).ok_or_else(|| missing_grant_error(&env, grant_id))?;
+        if !schedule.tranches.is_empty() {
+            return Ok(schedule.tranches);
+        }
+        Ok(Vec::from_array(
+            &env,
+            [VestingTranche {
+                amount: schedule.total_amount,
+                start: schedule.start,
+                duration: schedule.duration,
+                cliff: schedule.cliff,
+            }],
+        ))
+    }
+
+    /// Admin-only. Permanently stops a vesting grant, e.g. when the contributor leaves. What
+    /// has vested so far stays claimable through claim_vested; the unvested remainder is
+    /// returned to the admin rather than the treasury. Returns the refunded amount, which is
+    /// zero for a fully vested schedule.
+    pub fn revoke_vesting(env: Env, grant_id: u64) -> Result<i128, Error> {
+        let admin = require_admin_auth(&env)?;
+        ensure_not_paused(&env, "revoke_vesting")?;
+        let schedule =
+            read_vesting(&env, grant_id).ok_or_else(|| missing_grant_error(&env, grant_id))?;
+        let unvested = stop_vesting(
+            &env,
+            admin.clone(),
+            grant_id,
+            schedule,
+            HistoryAction::Revoked,
+        )?;
+        if unvested > 0 {
+            pay_from_contract(&env, &admin, unvested)?;
+        }
+        Ok(unvested)
+    }
+
+    /// Read-only. Tokens per second the grant is currently paying out, for display: the flow
+    /// rate of a streaming grant, or the average rate of a vesting grant rounded down (per
+    /// ledger for a LedgerSequence schedule). Zero once the grant is paused, cancelled or
+    /// completed.
+    pub fn get_stream_rate(env: Env, grant_id: u64) -> Result<i128, Error> {
+        if let Some(schedule) = read_vesting(&env, grant_id) {
+            if schedule.status != GrantStatus::Active
+                || schedule.paused_at.is_some()
+                || schedule.duration == 0
+            {
+                return Ok(0);
+            }
+            return Ok(schedule.total_amount / i128::from(schedule.duration));
+        }
+        let grant = read_grant(&env, grant_id)?;
+        if grant.status != GrantStatus::Active {
+            return Ok(0);
+        }
+        Ok(grant.flow_rate)
+    }
+
+    /// Admin-only. Chooses the curve a vesting grant follows. Only allowed before the schedule
+    /// starts, while nothing has vested under any curve.
+    pub fn set_vesting_curve(
+        env: Env,
+        grant_id: u64,
+        curve: VestingCurveData,
+    ) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        ensure_terms_unlocked(&env, grant_id)?;
+        let mut schedule = read_unstarted_vesting(&env, grant_id)?;
+        schedule.curve = curve;
+        write_vesting(&env, grant_id, &schedule)?;
+        record_history(&env, grant_id, HistoryAction::CurveUpdated, admin, None);
+        Ok(())
+    }
+
+    /// Admin-only. Chooses whether a vesting grant's vested amount rounds down or up. Only
+    /// allowed before the schedule starts.
+    pub fn set_vesting_rounding(
+        env: Env,
+        grant_id: u64,
+        rounding: RoundingData,
+    ) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        ensure_terms_unlocked(&env, grant_id)?;
+        let mut schedule = read_unstarted_vesting(&env, grant_id)?;
+        schedule.rounding = rounding;
+        write_vesting(&env, grant_id, &schedule)?;
+        record_history(&env, grant_id, HistoryAction::RoundingUpdated, admin, None);
+        Ok(())
+    }
+
+    /// Read-only. The vesting grant's unlock table: the vested amount at `points` evenly spaced
+    /// timestamps from start to the nominal end, as its cliff, curve, pauses and any
+    /// cancellation stand now. `points` is clamped to 2..=vesting::MAX_PREVIEW_POINTS.
+    pub fn preview_vesting_schedule(
+        env: Env,
+        grant_id: u64,
+        points: u32,
+    ) -> Result<Vec<(u64, i128)>, Error> {
+        let schedule =
+            read_vesting(&env, grant_id).ok_or_else(|| missing_grant_error(&env, grant_id))?;
+        let mut table = Vec::new(&env);
+        for now in vesting::sample_times(schedule.start, schedule.duration, points) {
+            table.push_back((now, vested_amount(&schedule, now)?));
+        }
+        Ok(table)
+    }
+
+    /// Admin-only. Freezes an active vesting grant: nothing further vests, and the cliff and
+    /// end move back by however long the pause lasts. Already vested funds stay claimable.
+    pub fn pause_vesting(env: Env, grant_id: u64) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        let mut schedule =
+            read_vesting(&env, grant_id).ok_or_else(|| missing_grant_error(&env, grant_id))?;
+        if schedule.status != GrantStatus::Active || schedule.paused_at.is_some() {
+            return Err(Error::InvalidState);
+        }
+
+        schedule.paused_at = Some(vesting_now(&env, &schedule));
+        write_vesting(&env, grant_id, &schedule)?;
+        record_history(&env, grant_id, HistoryAction::Paused, admin, None);
+        Ok(())
+    }
+
+    /// Admin-only. Ends a pause started by pause_vesting.
+    pub fn resume_vesting(env: Env, grant_id: u64) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        let mut schedule =
+            read_vesting(&env, grant_id).ok_or_else(|| missing_grant_error(&env, grant_id))?;
+        if schedule.status != GrantStatus::Active || schedule.paused_at.is_none() {
+            return Err(Error::InvalidState);
+        }
+
+        let now = vesting_now(&env, &schedule);
+        end_pause(&mut schedule, now);
+        write_vesting(&env, grant_id, &schedule)?;
+        record_history(&env, grant_id, HistoryAction::Resumed, admin, None);
+        Ok(())
+    }
+
+    /// Recipient-only. Transfers everything vested but not yet claimed and returns the amount.
+    /// Fails with InsufficientFunding, claiming nothing, if the contract holds less than that.
+    pub fn claim_vested(env: Env, grant_id: u64) -> Result<i128, Error> {
+        Ok(settle_vested_claim(&env, grant_id)?.requested)
+    }
+
+    /// Like claim_vested, but returns a WithdrawReceipt with what the recipient's balance
+    /// actually grew by.
+    pub fn claim_vested_with_receipt(env: Env, grant_id: u64) -> Result<WithdrawReceipt, Error> {
+        settle_vested_claim(&env, grant_id)
+    }
+
+    /// Admin-only. Reassigns a grant to a new recipient and moves it between grantee indexes.
+    pub fn update_recipient(env: Env, grant_id: u64, new_recipient: Address) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        let grant = read_grant(&env, grant_id)?;
+
+        if grant.status == GrantStatus::Cancelled || is_archived(&env, grant_id) {
+            return Err(Error::InvalidState);
+        }
+
+        reassign_recipient(&env, grant_id, grant, new_recipient, admin)
+    }
+
+    /// Current recipient only. Hands the grant, including anything already accrued, to
+    /// `new_grantee` without admin involvement, unless the admin has locked transfers for
+    /// the grant.
+    pub fn transfer_grantee_rights(
+        env: Env,
+        grant_id: u64,
+        new_grantee: Address,
+    ) -> Result<(), Error> {
+        let grant = read_grant(&env, grant_id)?;
+        grant.recipient.require_auth();
+
+        let locked: bool =
+            read_record(&env, &DataKey::GranteeTransferLocked(grant_id)).unwrap_or(false);
+        if locked {
+            return Err(Error::NotAuthorized);
+        }
+        if grant.status == GrantStatus::Cancelled || is_archived(&env, grant_id) {
+            return Err(Error::InvalidState);
+        }
+
+        let actor = grant.recipient.clone();
+        reassign_recipient(&env, grant_id, grant, new_grantee, actor)
+    }
+
+    /// Admin-only. Permanently freezes a grant's terms: update_rate, reschedule_grant,
+    /// set_vesting_curve and set_vesting_rounding then fail with TermsLocked. Withdrawals,
+    /// claims and cancellation keep working.
+    pub fn lock_terms(env: Env, grant_id: u64) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        if read_vesting(&env, grant_id).is_none() {
+            read_grant(&env, grant_id)?;
+        }
+        write_record(&env, &DataKey::TermsLocked(grant_id), &true);
+        record_history(&env, grant_id, HistoryAction::TermsLocked, admin, None);
+        Ok(())
+    }
+
+    pub fn is_terms_locked(env: Env, grant_id: u64) -> bool {
+        has_record(&env, &DataKey::TermsLocked(grant_id))
+    }
+
+    /// Admin-only. Blocks or re-allows transfer_grantee_rights for a grant.
+    pub fn set_grantee_transfer_locked(env: Env, grant_id: u64, locked: bool) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        read_grant(&env, grant_id)?;
+        let key = DataKey::GranteeTransferLocked(grant_id);
+        if locked {
+            write_record(&env, &key, &true);
+        } else {
+            remove_record(&env, &key);
+        }
+        record_history(
+            &env,
+            grant_id,
+            HistoryAction::TransferLockUpdated,
+            admin,
+            None,
+        );
+        Ok(())
+    }
+
+    /// Admin-only. Stops the stream. Whatever has already accrued stays claimable by the
+    /// recipient; the unstreamed remainder is owed to the treasury and paid by claim_refund.
+    ///
+    /// A vesting grant stops vesting at the current time; what has vested stays claimable.
+    pub fn cancel_grant(env: Env, grant_id: u64) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        ensure_not_paused(&env, "cancel_grant")?;
+        if let Some(schedule) = read_vesting(&env, grant_id) {
+            return cancel_vesting(&env, admin, grant_id, schedule);
+        }
+        let grant = read_grant(&env, grant_id)?;
+        cancel(&env, admin, grant_id, grant)
+    }
+
+    /// Admin-only. Like cancel_grant, but returns `false` instead of failing when the grant is
+    /// already cancelled, so retried submissions are harmless. Returns `true` if it cancelled.
+    pub fn cancel_grant_idempotent(env: Env, grant_id: u64) -> Result<bool, Error> {
+        let admin = require_admin_auth(&env)?;
+        ensure_not_paused(&env, "cancel_grant")?;
+        if let Some(schedule) = read_vesting(&env, grant_id) {
+            if schedule.status == GrantStatus::Cancelled {
+                return Ok(false);
+            }
+            cancel_vesting(&env, admin, grant_id, schedule)?;
+            return Ok(true);
+        }
+        let grant = read_grant(&env, grant_id)?;
+        if grant.status == GrantStatus::Cancelled {
+            return Ok(false);
+        }
+        cancel(&env, admin, grant_id, grant)?;
+        Ok(true)
+    }
+
+    /// Admin-only. Transfers the refund a cancellation left owed to the treasury, or to the
+    /// admin for a declined grant, and returns the amount. Fails with NothingToClaim when
+    /// nothing is owed.
+    pub fn claim_refund(env: Env, grant_id: u64) -> Result<i128, Error> {
+        let admin = require_admin_auth(&env)?;
+        ensure_not_paused(&env, "claim_refund")?;
+        if !grant_id_taken(&env, grant_id) {
+            return Err(Error::GrantNotFound);
+        }
+        let amount = read_refund_owed(&env, grant_id);
+        if amount == 0 {
+            return Err(Error::NothingToClaim);
+        }
+
+        remove_record(&env, &DataKey::RefundOwed(grant_id));
+        add_to_total(&env, &DataKey::RefundsOwed, -amount)?;
+        match read_record::<Address>(&env, &DataKey::RefundTo(grant_id)) {
+            Some(to) => {
+                remove_record(&env, &DataKey::RefundTo(grant_id));
+                pay_from_contract(&env, &to, amount)?;
+            }
+            None => refund_to_treasury(&env, amount)?,
+        }
+        record_history(
+            &env,
+            grant_id,
+            HistoryAction::RefundClaimed,
+            admin,
+            Some(amount),
+        );
+        Ok(amount)
+    }
+
+    /// Read-only. Refund owed for a cancelled or declined grant, zero once claimed.
+    pub fn get_refund_owed(env: Env, grant_id: u64) -> i128 {
+        read_refund_owed(&env, grant_id)
+    }
+
+    /// Grant IDs created under `admin`, paginated by `start` and `limit` (capped at MAX_PAGE_SIZE).
+    pub fn get_grants_by_admin(env: Env, admin: Address, start: u32, limit: u32) -> Vec<u64> {
+        let ids = read_index(&env, &DataKey::AdminGrants(admin));
+        paginate(&env, &ids, start, limit)
+    }
+
+    /// Read-only. How much of each token the grant has released to its recipient: withdrawals
+    /// and vested claims in the grant token, plus a bonus once paid. Amounts are the ones the
+    /// contract sent, before any fee the token takes.
+    pub fn get_released_by_token(env: Env, grant_id: u64) -> Result<Map<Address, i128>, Error> {
+        if !grant_id_taken(&env, grant_id) {
+            return Err(Error::GrantNotFound);
+        }
+        Ok(
+            read_record(&env, &DataKey::ReleasedByToken(grant_id))
+                .unwrap_or_else(|| Map::new(&env)),
+        )
+    }
+
+    /// Read-only. What the contract still owes across every grant created under `admin`: the
+    /// sum of each grant's unstreamed and unwithdrawn balance plus any refund owed, as counted
+    /// by rescue_tokens. Zero for an admin with no grants.
+    pub fn get_admin_outstanding(env: Env, admin: Address) -> Result<i128, Error> {
+        let ids = read_index(&env, &DataKey::AdminGrants(admin));
+        let mut total = 0_i128;
+        for grant_id in ids.iter() {
+            total = total
+                .checked_add(outstanding_obligation(&env, grant_id)?)
+                .ok_or(Error::MathOverflow)?;
+        }
+        Ok(total)
+    }
+
+    /// Grant IDs by stored status, paginated by `start` and `limit` (capped at MAX_PAGE_SIZE).
+    /// A grant that has fully streamed but not been touched since is still listed as Active
+    /// until a call settles it.
+    pub fn get_grants_by_status(env: Env, status: GrantStatus, start: u32, limit: u32) -> Vec<u64> {
+        let ids = read_index(&env, &DataKey::GrantsByStatus(status));
+        paginate(&env, &ids, start, limit)
+    }
+
+    /// Admin-only. Replaces the grant's category tags (at most MAX_TAGS, duplicates ignored).
+    /// Tags can only be changed before the recipient's first withdrawal.
+    pub fn set_grant_tags(env: Env, grant_id: u64, tags: Vec<Symbol>) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        let grant = read_grant(&env, grant_id)?;
+        if grant.status != GrantStatus::Active || grant.withdrawn != 0 {
+            return Err(Error::InvalidState);
+        }
+
+        let mut unique: Vec<Symbol> = Vec::new(&env);
+        for tag in tags.iter() {
+            if !unique.contains(&tag) {
+                unique.push_back(tag);
+            }
+        }
+        if unique.len() > MAX_TAGS {
+            return Err(Error::TooManyTags);
+        }
+
+        for tag in Self::get_grant_tags(env.clone(), grant_id).iter() {
+            index_remove(&env, &DataKey::TagGrants(tag), grant_id);
+        }
+        for tag in unique.iter() {
+            index_add(&env, &DataKey::TagGrants(tag), grant_id);
+        }
+        write_record(&env, &DataKey::GrantTags(grant_id), &unique);
+        record_history(&env, grant_id, HistoryAction::TagsUpdated, admin, None);
+        Ok(())
+    }
+
+    /// Admin-only. Deletes a finished grant, streaming or vesting, to reclaim storage. Only
+    /// Completed or Cancelled grants with nothing left to withdraw and no refund owed can be
+    /// archived. The grant's GrantExport is published first in an `archived` event, then every
+    /// record of the grant and its index entries are removed, and its token's stats stop
+    /// counting anything as escrowed for it. A GrantTombstone holding the final status and the
+    /// export's hash stays behind, so the id is never reused and later calls on it fail with
+    /// GrantArchived. Archiving an archived grant again does nothing.
+    pub fn archive_grant(env: Env, grant_id: u64) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        if has_record(&env, &DataKey::Tombstone(grant_id)) {
+            return Ok(());
+        }
+        // The grant's (stored, archived) contributions to its token's stats.
+        let (status, recipient, claimable, stats) = match read_vesting(&env, grant_id) {
+            Some(schedule) => {
+                let stored = vesting_stats_contribution(&schedule)?;
+                (
+                    schedule.status,
+                    schedule.recipient.clone(),
+                    vested_claimable(&env, &schedule)?,
+                    (stored, (0, stored.1, stored.2)),
+                )
+            }
+            None => {
+                let stored = read_grant(&env, grant_id)?;
+                let grant = preview_grant_at_now(&env, &stored)?;
+                let (_, released, withdrawn) = stats_contribution(&grant)?;
+                (
+                    grant.status,
+                    grant.recipient,
+                    grant.claimable,
+                    (stats_contribution(&stored)?, (0, released, withdrawn)),
+                )
+            }
+        };
+        if status == GrantStatus::Active || claimable != 0 || read_refund_owed(&env, grant_id) != 0
+        {
+            return Err(Error::InvalidState);
+        }
+        let export = Self::export_grant(env.clone(), grant_id)?;
+        let tombstone = GrantTombstone {
+            status,
+            export_hash: env.crypto().sha256(&export.clone().to_xdr(&env)),
+        };
+
+        index_remove(&env, &DataKey::AdminGrants(admin), grant_id);
+        index_remove(&env, &DataKey::GranteeGrants(recipient), grant_id);
+        index_remove(&env, &DataKey::GrantsByStatus(status), grant_id);
+        // Ids are indexed under their stored status, which may still be Active.
+        index_remove(&env, &DataKey::GrantsByStatus(GrantStatus::Active), grant_id);
+        for tag in Self::get_grant_tags(env.clone(), grant_id).iter() {
+            index_remove(&env, &DataKey::TagGrants(tag), grant_id);
+        }
+
+        if let Some(bonus) = read_record::<GrantBonus>(&env, &DataKey::GrantBonus(grant_id)) {
+            add_to_total(&env, &DataKey::PendingBonuses(bonus.token), -bonus.amount)?;
+        }
+        for key in grant_record_keys(grant_id) {
+            remove_record(&env, &key);
+        }
+        apply_token_stats(&env, stats.0, stats.1)?;
+        write_record(&env, &DataKey::Archived(grant_id), &true);
+        write_record(&env, &DataKey::Tombstone(grant_id), &tombstone);
+
+        env.events()
+            .publish((symbol_short!("archived"), grant_id), export);
+        Ok(())
+    }
+
+    /// Number of grants that reached Completed while `grantee` was their recipient. Grants
+    /// completed before this counter existed are not included.
+    pub fn get_completed_count(env: Env, grantee: Address) -> u64 {
+        read_record(&env, &DataKey::CompletedCount(grantee)).unwrap_or(0)
+    }
+
+    pub fn get_tombstone(env: Env, grant_id: u64) -> Result<GrantTombstone, Error> {
+        read_record(&env, &DataKey::Tombstone(grant_id)).ok_or(Error::GrantNotFound)
+    }
+
+    pub fn is_archived(env: Env, grant_id: u64) -> bool {
+        is_archived(&env, grant_id)
+    }
+
+    /// All grant ids in creation order, paginated by `start` and `limit` (capped at
+    /// MAX_PAGE_SIZE). Archived grants are skipped, and not counted by `start`, unless
+    /// `include_archived` is set.
+    pub fn list_grants(env: Env, start: u32, limit: u32, include_archived: bool) -> Vec<u64> {
+        let ids = read_grant_ids(&env);
+        let limit = limit.min(MAX_PAGE_SIZE);
+        let mut page = Vec::new(&env);
+        let mut skipped = 0;
+        for grant_id in ids.iter() {
+            if page.len() >= limit {
+                break;
+            }
+            if !include_archived && is_archived(&env, grant_id) {
+                continue;
+            }
+            if skipped < start {
+                skipped += 1;
+                continue;
+            }
+            page.push_back(grant_id);
+        }
+        page
+    }
+
+    pub fn get_grant_tags(env: Env, grant_id: u64) -> Vec<Symbol> {
+        read_record(&env, &DataKey::GrantTags(grant_id)).unwrap_or_else(|| Vec::new(&env))
+    }
+
+    /// Grant IDs carrying `tag`, paginated by `start` and `limit` (capped at MAX_PAGE_SIZE).
+    pub fn get_grants_by_tag(env: Env, tag: Symbol, start: u32, limit: u32) -> Vec<u64> {
+        let ids = read_index(&env, &DataKey::TagGrants(tag));
+        paginate(&env, &ids, start, limit)
+    }
+
+    /// Admin-only. Sets or clears the contract notified after each withdrawal from this grant.
+    pub fn set_grant_hook(env: Env, grant_id: u64, hook: Option<Address>) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        read_grant(&env, grant_id)?;
+        let key = DataKey::GrantHook(grant_id);
+        match hook {
+            Some(hook) => write_record(&env, &key, &hook),
+            None => remove_record(&env, &key),
+        }
+        record_history(&env, grant_id, HistoryAction::HookUpdated, admin, None);
+        Ok(())
+    }
+
+    /// Admin-only. When enabled, the admin rather than the recipient triggers withdrawals from
+    /// this grant (payroll-style disbursement). Off by default.
+    pub fn set_admin_withdraws(env: Env, grant_id: u64, enabled: bool) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        read_grant(&env, grant_id)?;
+        let key = DataKey::AdminWithdraws(grant_id);
+        if enabled {
+            write_record(&env, &key, &true);
+        } else {
+            remove_record(&env, &key);
+        }
+        record_history(
+            &env,
+            grant_id,
+            HistoryAction::AdminWithdrawsUpdated,
+            admin,
+            None,
+        );
+        Ok(())
+    }
+
+    /// Admin-only. Sets or clears the PriceOracle used by get_value_in_reference, for a
+    /// streaming or a vesting grant.
+    pub fn set_grant_oracle(env: Env, grant_id: u64, oracle: Option<Address>) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        ensure_grant_exists(&env, grant_id)?;
+        let key = DataKey::GrantOracle(grant_id);
+        match oracle {
+            Some(oracle) => write_record(&env, &key, &oracle),
+            None => remove_record(&env, &key),
+        }
+        record_history(&env, grant_id, HistoryAction::OracleUpdated, admin, None);
+        Ok(())
+    }
+
+    /// Read-only. The grant's outstanding amount (what the contract still owes the recipient),
+    /// valued in `reference_token` at the price reported by the grant's oracle. Rounds down.
+    /// For a vesting grant that is the unclaimed part of its total, or of what vested before
+    /// it was cancelled.
+    pub fn get_value_in_reference(
+        env: Env,
+        grant_id: u64,
+        reference_token: Address,
+    ) -> Result<i128, Error> {
+        let (outstanding, _, _) = match read_vesting(&env, grant_id) {
+            Some(schedule) => vesting_stats_contribution(&schedule)?,
+            None => stats_contribution(&preview_grant_at_now(&env, &read_grant(&env, grant_id)?)?)?,
+        };
+        let oracle: Address =
+            read_record(&env, &DataKey::GrantOracle(grant_id)).ok_or(Error::OracleNotSet)?;
+
+        let grant_token = read_grant_token(&env)?;
+        let price = PriceOracleClient::new(&env, &oracle).price(&grant_token, &reference_token);
+        if price < 0 {
+            return Err(Error::InvalidAmount);
+        }
+        outstanding
+            .checked_mul(price)
+            .map(|value| value / PRICE_SCALE)
+            .ok_or(Error::MathOverflow)
+    }
+
+    /// Admin-only. Sets or clears the cap on how much the recipient may withdraw per period,
+    /// limiting the damage a compromised recipient key can do. Changing the limit restarts
+    /// the current window.
+    pub fn set_withdraw_limit(
+        env: Env,
+        grant_id: u64,
+        limit: Option<WithdrawLimit>,
+    ) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        read_grant(&env, grant_id)?;
+        let key = DataKey::WithdrawLimit(grant_id);
+        match limit {
+            Some(limit) => {
+                if limit.max_per_period <= 0 || limit.period == 0 {
+                    return Err(Error::InvalidAmount);
+                }
+                write_record(&env, &key, &limit);
+            }
+            None => remove_record(&env, &key),
+        }
+        remove_record(&env, &DataKey::WithdrawWindow(grant_id));
+        record_history(
+            &env,
+            grant_id,
+            HistoryAction::WithdrawLimitUpdated,
+            admin,
+            None,
+        );
+        Ok(())
+    }
+
+    /// Admin-only. When `allowed` is false, grants can no longer be created for, or reassigned
+    /// to, the admin's own address. Self-grants are allowed by default; existing ones are
+    /// left alone.
+    pub fn set_allow_self_grants(env: Env, allowed: bool) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        env.storage()
+            .instance()
+            .set(&DataKey::AllowSelfGrants, &allowed);
+        Ok(())
+    }
+
+    pub fn get_allow_self_grants(env: Env) -> bool {
+        env.storage()
+            .instance()
+            .get(&DataKey::AllowSelfGrants)
+            .unwrap_or(true)
+    }
+
+    /// Admin-only. Grants whose unpaid remainder falls to `threshold` or below are marked Completed.
+    /// Defaults to 0 (strict). Intended for fee-on-transfer tokens that leave a few units short.
+    pub fn set_dust_threshold(env: Env, threshold: i128) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        if threshold < 0 {
+            return Err(Error::InvalidAmount);
+        }
+        env.storage()
+            .instance()
+            .set(&DataKey::DustThreshold, &threshold);
+        Ok(())
+    }
+
+    /// Grant IDs currently payable to `grantee`, paginated by `start` and `limit` (capped at MAX_PAGE_SIZE).
+    pub fn get_grants_by_grantee(env: Env, grantee: Address, start: u32, limit: u32) -> Vec<u64> {
+        let ids = read_index(&env, &DataKey::GranteeGrants(grantee));
+        paginate(&env, &ids, start, limit)
+    }
+
+    /// Up to `limit` history entries (capped at MAX_PAGE_SIZE) starting at `start`, oldest first.
+    pub fn get_history(
+        env: Env,
+        grant_id: u64,
+        start: u32,
+        limit: u32,
+    ) -> Result<Vec<HistoryEntry>, Error> {
+        if !has_record(&env, &DataKey::Grant(grant_id))
+            && !has_record(&env, &DataKey::Vesting(grant_id))
+        {
+            return Err(missing_grant_error(&env, grant_id));
+        }
+        let history = read_history(&env, grant_id);
+        Ok(paginate(&env, &history, start, limit))
+    }
 
-    let elapsed = now - grant.last_update_ts;
-    grant.last_update_ts = now;
-
-    if grant.status != GrantStatus::Active || elapsed == 0 || grant.flow_rate == 0 {
-        return Ok(());
+    /// Read-only. Seconds since the grant was created.
+    pub fn get_grant_age(env: Env, grant_id: u64) -> Result<u64, Error> {
+        let created_at: u64 = read_record(&env, &DataKey::CreatedAt(grant_id))
+            .ok_or_else(|| missing_grant_error(&env, grant_id))?;
+        Ok(env.ledger().timestamp().saturating_sub(created_at))
     }
 
-    if grant.flow_rate < 0 {
-        return Err(Error::InvalidRate);
+    /// Read-only. Seconds since the grant's last recorded action, i.e. its newest history
+    /// entry. History always keeps at least that entry.
+    pub fn get_idle_time(env: Env, grant_id: u64) -> Result<u64, Error> {
+        let last_activity_at = read_history(&env, grant_id)
+            .last()
+            .ok_or_else(|| missing_grant_error(&env, grant_id))?
+            .timestamp;
+        Ok(env.ledger().timestamp().saturating_sub(last_activity_at))
     }
 
-    let elapsed_i128 = i128::from(elapsed);
-    let accrued = grant
-        .flow_rate
-        .checked_mul(elapsed_i128)
-        .ok_or(Error::MathOverflow)?;
+    /// Admin-only. Sets how many history entries are kept per grant; must be at least 1.
+    pub fn set_history_limit(env: Env, limit: u32) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        if limit == 0 {
+            return Err(Error::InvalidAmount);
+        }
+        env.storage().instance().set(&DataKey::HistoryLimit, &limit);
+        Ok(())
+    }
 
-    let accounted = grant
-        .withdrawn
-        .checked_add(grant.claimable)
-        .ok_or(Error::MathOverflow)?;
+    /// Admin-only. Sets how far, in seconds, a timestamp-based vesting start may be from the
+    /// time the grant is created, in either direction. None removes the limit. Defaults to 10
+    /// years.
+    pub fn set_max_start_offset(env: Env, max_offset: Option<u64>) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        env.storage()
+            .instance()
+            .set(&DataKey::MaxStartOffset, &max_offset);
+        Ok(())
+    }
 
-    if accounted > grant.total_amount {
-        return Err(Error::InvalidState);
+    pub fn get_max_start_offset(env: Env) -> Option<u64> {
+        read_max_start_offset(&env)
     }
 
-    let remaining = grant
-        .total_amount
-        .checked_sub(accounted)
-        .ok_or(Error::MathOverflow)?;
+    /// Admin-only. Controls whether cancelled grants are removed from the admin index. Off by default so cancelled grants stay visible historically.
+    pub fn set_prune_cancelled(env: Env, prune: bool) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        env.storage().instance().set(&DataKey::PruneCancelled, &prune);
+        Ok(())
+    }
 
-    let delta = if accrued > remaining {
-        remaining
-    } else {
-        accrued
-    };
+    pub fn grant_exists(env: Env, grant_id: u64) -> bool {
+        has_record(&env, &DataKey::Grant(grant_id)) || has_record(&env, &DataKey::Vesting(grant_id))
+    }
 
-    grant.claimable = grant
-        .claimable
-        .checked_add(delta)
-        .ok_or(Error::MathOverflow)?;
+    /// Status as of the current ledger time, so a fully streamed grant reports Completed. A
+    /// vesting grant only completes once everything has been claimed.
+    pub fn get_grant_status(env: Env, grant_id: u64) -> Result<GrantStatus, Error> {
+        if let Some(schedule) = read_vesting(&env, grant_id) {
+            return Ok(schedule.status);
+        }
+        let grant = read_grant(&env, grant_id)?;
+        Ok(preview_grant_at_now(&env, &grant)?.status)
+    }
 
-    let new_accounted = grant
-        .withdrawn
-        .checked_add(grant.claimable)
-        .ok_or(Error::MathOverflow)?;
+    /// Aggregate escrowed, released and withdrawn amounts across all grants in `token`.
+    pub fn get_token_stats(env: Env, token: Address) -> TokenStats {
+        read_record(&env, &DataKey::TokenStats(token)).unwrap_or_default()
+    }
 
-    if new_accounted == grant.total_amount {
-        grant.status = GrantStatus::Completed;
+    pub fn get_grant(env: Env, grant_id: u64) -> Result<Grant, Error> {
+        let grant = read_grant(&env, grant_id)?;
+        preview_grant_at_now(&env, &grant)
     }
 
-    Ok(())
-}
+    /// Batch form of get_grant for up to MAX_PAGE_SIZE ids. Results are positional, with
+    /// `None` for ids that do not exist.
+    pub fn get_grants(env: Env, grant_ids: Vec<u64>) -> Result<Vec<Option<Grant>>, Error> {
+        if grant_ids.len() > MAX_PAGE_SIZE {
+            return Err(Error::BatchTooLarge);
+        }
 
-fn preview_grant_at_now(env: &Env, grant: &Grant) -> Result<Grant, Error> {
-    let mut preview = grant.clone();
-    settle_grant(&mut preview, env.ledger().timestamp())?;
-    Ok(preview)
-}
+        let mut grants = Vec::new(&env);
+        for grant_id in grant_ids.iter() {
+            let grant = match read_grant(&env, grant_id) {
+                Ok(grant) => Some(preview_grant_at_now(&env, &grant)?),
+                Err(_) => None,
+            };
+            grants.push_back(grant);
+        }
+        Ok(grants)
+    }
 
-#[contractimpl]
-impl GrantContract {
-    pub fn initialize(
-        env: Env,
-        admin: Address,
-        grant_token: Address,
-        treasury: Address,
-    ) -> Result<(), Error> {
-        if env.storage().instance().has(&DataKey::Admin) {
-            return Err(Error::AlreadyInitialized);
+    /// Read-only monitoring check that the stored grant is internally consistent: amounts are
+    /// non-negative, withdrawn <= released <= total, and the status matches the amounts.
+    /// Returns false rather than failing so monitors get a clean signal.
+    pub fn check_invariants(env: Env, grant_id: u64) -> Result<bool, Error> {
+        if let Some(schedule) = read_vesting(&env, grant_id) {
+            return Ok(vesting_is_consistent(
+                &schedule,
+                vesting_now(&env, &schedule),
+            ));
         }
-        admin.require_auth();
-        env.storage().instance().set(&DataKey::Admin, &admin);
-        env.storage().instance().set(&DataKey::GrantToken, &grant_token);
-        env.storage().instance().set(&DataKey::Treasury, &treasury);
-        env.storage()
-            .instance()
-            .set(&DataKey::GrantIds, &Vec::<u64>::new(&env));
-        Ok(())
+        let grant = read_grant(&env, grant_id)?;
+        Ok(grant_is_consistent(&grant, read_dust_threshold(&env)))
     }
 
-    pub fn create_grant(
-        env: Env,
-        grant_id: u64,
-        recipient: Address,
-        total_amount: i128,
-        flow_rate: i128,
-    ) -> Result<(), Error> {
+    /// Admin-only. Sets how many decimal places get_total_scaled and get_released_scaled
+    /// split the grant's amounts at, e.g. 7 for a token with 7 decimals. At most
+    /// MAX_AMOUNT_SCALE.
+    pub fn set_amount_scale(env: Env, grant_id: u64, scale: u32) -> Result<(), Error> {
         require_admin_auth(&env)?;
-
-        if total_amount <= 0 {
+        read_grant(&env, grant_id)?;
+        if scale > MAX_AMOUNT_SCALE {
             return Err(Error::InvalidAmount);
         }
+        write_record(&env, &DataKey::AmountScale(grant_id), &scale);
+        Ok(())
+    }
 
-        if flow_rate < 0 {
-            return Err(Error::InvalidRate);
-        }
-
-        let key = DataKey::Grant(grant_id);
-        if env.storage().instance().has(&key) {
-            return Err(Error::GrantAlreadyExists);
+    /// Admin-only. Sets the largest fee, in basis points of the claimed amount, the grant
+    /// token may take from a vesting claim; a claim whose recipient receives less fails with
+    /// ExcessiveFee. None, the default, accepts any fee.
+    pub fn set_max_fee_bps(env: Env, grant_id: u64, max_fee_bps: Option<u32>) -> Result<(), Error> {
+        require_admin_auth(&env)?;
+        read_vesting(&env, grant_id).ok_or_else(|| missing_grant_error(&env, grant_id))?;
+        let key = DataKey::MaxFeeBps(grant_id);
+        match max_fee_bps {
+            Some(bps) => {
+                vesting::validate_bps(bps).map_err(|_| Error::InvalidAmount)?;
+                write_record(&env, &key, &bps);
+            }
+            None => remove_record(&env, &key),
         }
-
-        let now = env.ledger().timestamp();
-        let grant = Grant {
-            recipient,
-            total_amount,
-            withdrawn: 0,
-            claimable: 0,
-            flow_rate,
-            last_update_ts: now,
-            rate_updated_at: now,
-            last_claim_time: now,
-            status: GrantStatus::Active,
-        };
-
-        env.storage().instance().set(&key, &grant);
-        let mut ids = read_grant_ids(&env);
-        ids.push_back(grant_id);
-        env.storage().instance().set(&DataKey::GrantIds, &ids);
         Ok(())
     }
 
-    pub fn cancel_grant(env: Env, grant_id: u64) -> Result<(), Error> {
+    /// Admin-only. Turns off the grant-token check made at grant creation, for tokens that
+    /// do not implement decimals().
+    pub fn set_skip_token_validation(env: Env, skip: bool) -> Result<(), Error> {
         require_admin_auth(&env)?;
-        let mut grant = read_grant(&env, grant_id)?;
+        env.storage()
+            .instance()
+            .set(&DataKey::SkipTokenValidation, &skip);
+        Ok(())
+    }
 
-        if grant.status != GrantStatus::Active {
-            return Err(Error::InvalidState);
-        }
+    /// Decimals of the grant token, for display; None until a grant has been created with
+    /// token validation on.
+    pub fn get_token_decimals(env: Env) -> Option<u32> {
+        env.storage().instance().get(&DataKey::TokenDecimals)
+    }
 
-        settle_grant(&mut grant, env.ledger().timestamp())?;
-        grant.flow_rate = 0;
-        grant.status = GrantStatus::Cancelled;
-        write_grant(&env, grant_id, &grant);
+    pub fn get_max_fee_bps(env: Env, grant_id: u64) -> Option<u32> {
+        read_record(&env, &DataKey::MaxFeeBps(grant_id))
+    }
 
-        Ok(())
+    pub fn get_amount_scale(env: Env, grant_id: u64) -> Result<u32, Error> {
+        read_grant(&env, grant_id)?;
+        Ok(read_amount_scale(&env, grant_id))
     }
 
-    pub fn get_grant(env: Env, grant_id: u64) -> Result<Grant, Error> {
+    /// Read-only. The grant's total_amount as (whole units, remainder) at its amount scale.
+    pub fn get_total_scaled(env: Env, grant_id: u64) -> Result<(i128, i128), Error> {
         let grant = read_grant(&env, grant_id)?;
-        preview_grant_at_now(&env, &grant)
+        Ok(split_scaled(
+            grant.total_amount,
+            read_amount_scale(&env, grant_id),
+        ))
+    }
+
+    /// Read-only. The released amount of get_progress as (whole units, remainder) at the
+    /// grant's amount scale.
+    pub fn get_released_scaled(env: Env, grant_id: u64) -> Result<(i128, i128), Error> {
+        let progress = Self::get_progress(env.clone(), grant_id)?;
+        Ok(split_scaled(
+            progress.released_amount,
+            read_amount_scale(&env, grant_id),
+        ))
+    }
+
+    pub fn get_progress(env: Env, grant_id: u64) -> Result<GrantProgress, Error> {
+        if let Some(schedule) = read_vesting(&env, grant_id) {
+            let released_amount = vested_amount(&schedule, vesting_now(&env, &schedule))?;
+            return Ok(GrantProgress {
+                total_amount: schedule.total_amount,
+                released_amount,
+                withdrawn_amount: schedule.claimed,
+                released_bps: ratio_bps(released_amount, schedule.total_amount),
+            });
+        }
+        let grant = read_grant(&env, grant_id)?;
+        let preview = preview_grant_at_now(&env, &grant)?;
+        let released_amount = preview
+            .withdrawn
+            .checked_add(preview.claimable)
+            .ok_or(Error::MathOverflow)?;
+        Ok(GrantProgress {
+            total_amount: preview.total_amount,
+            released_amount,
+            withdrawn_amount: preview.withdrawn,
+            released_bps: ratio_bps(released_amount, preview.total_amount),
+        })
+    }
+
+    /// Read-only. Bundles the grant with its history, every per-grant setting and the
+    /// contract-wide settings that govern it.
+    pub fn export_grant(env: Env, grant_id: u64) -> Result<GrantExport, Error> {
+        let grant = match read_vesting(&env, grant_id) {
+            Some(schedule) => GrantRecord::Vesting(schedule),
+            None => {
+                let grant = read_grant(&env, grant_id)?;
+                GrantRecord::Streaming(preview_grant_at_now(&env, &grant)?)
+            }
+        };
+        Ok(GrantExport {
+            grant_id,
+            grant,
+            history: read_history(&env, grant_id),
+            hook: read_optional_record(&env, &DataKey::GrantHook(grant_id)),
+            tags: Self::get_grant_tags(env.clone(), grant_id),
+            withdraw_limit: read_optional_record(&env, &DataKey::WithdrawLimit(grant_id)),
+            withdraw_window: read_optional_record(&env, &DataKey::WithdrawWindow(grant_id)),
+            admin_withdraws: has_record(&env, &DataKey::AdminWithdraws(grant_id)),
+            oracle: read_optional_record(&env, &DataKey::GrantOracle(grant_id)),
+            grantee_transfer_locked: read_record(&env, &DataKey::GranteeTransferLocked(grant_id))
+                .unwrap_or(false),
+            bonus: read_optional_record(&env, &DataKey::GrantBonus(grant_id)),
+            refund_owed: read_refund_owed(&env, grant_id),
+            refund_to: read_optional_record(&env, &DataKey::RefundTo(grant_id)),
+            created_at: read_record(&env, &DataKey::CreatedAt(grant_id)).unwrap_or(0),
+            amount_scale: read_amount_scale(&env, grant_id),
+            max_fee_bps: read_record(&env, &DataKey::MaxFeeBps(grant_id)),
+            released_by_token: read_record(&env, &DataKey::ReleasedByToken(grant_id))
+                .unwrap_or_else(|| Map::new(&env)),
+            terms_locked: has_record(&env, &DataKey::TermsLocked(grant_id)),
+            history_limit: read_history_limit(&env),
+            dust_threshold: read_dust_threshold(&env),
+            prune_cancelled: prune_cancelled(&env),
+        })
     }
 
     pub fn claimable(env: Env, grant_id: u64) -> Result<i128, Error> {
@@ -292,54 +3486,194 @@ impl GrantContract {
         Ok(preview.claimable)
     }
 
+    /// What the recipient can take out right now: the accrued balance, limited by the grant
+    /// tokens the contract actually holds.
+    pub fn get_claimable(env: Env, grant_id: u64) -> Result<i128, Error> {
+        Ok(Self::get_claimable_details(env, grant_id)?.amount)
+    }
+
+    /// get_claimable along with the unclamped figure and the reason for any shortfall. While
+    /// the contract is paused the amount is 0 unless the grant's withdrawal path is exempt.
+    pub fn get_claimable_details(env: Env, grant_id: u64) -> Result<ClaimableDetails, Error> {
+        let (accrued, function) = match read_vesting(&env, grant_id) {
+            Some(schedule) => (vested_claimable(&env, &schedule)?, "claim_vested"),
+            None => (
+                preview_grant_at_now(&env, &read_grant(&env, grant_id)?)?.claimable,
+                "withdraw",
+            ),
+        };
+        let contract_balance = token::Client::new(&env, &read_grant_token(&env)?)
+            .balance(&env.current_contract_address());
+
+        let (amount, reason) = if ensure_not_paused(&env, function).is_err() {
+            (0, ClaimableReason::Paused)
+        } else if accrued == 0 {
+            (0, ClaimableReason::NothingAccrued)
+        } else if contract_balance < accrued {
+            (contract_balance.max(0), ClaimableReason::Underfunded)
+        } else {
+            (accrued, ClaimableReason::Available)
+        };
+        Ok(ClaimableDetails {
+            amount,
+            accrued,
+            contract_balance,
+            reason,
+        })
+    }
+
+    /// Transfers `amount` of the grant's claimable balance to its recipient.
     pub fn withdraw(env: Env, grant_id: u64, amount: i128) -> Result<(), Error> {
+        Self::withdraw_with_receipt(env, grant_id, amount)?;
+        Ok(())
+    }
+
+    /// Like withdraw, but returns a WithdrawReceipt with what the recipient's balance actually
+    /// grew by.
+    pub fn withdraw_with_receipt(
+        env: Env,
+        grant_id: u64,
+        amount: i128,
+    ) -> Result<WithdrawReceipt, Error> {
         if amount <= 0 {
             return Err(Error::InvalidAmount);
         }
 
-        let mut grant = read_grant(&env, grant_id)?;
-
-        if grant.status == GrantStatus::Cancelled {
-            return Err(Error::InvalidState);
-        }
+        let grant = read_grant(&env, grant_id)?;
+        let actor = withdraw_authorizer(&env, grant_id, &grant)?;
+        actor.require_auth();
 
-        grant.recipient.require_auth();
+        withdraw_from(&env, grant_id, grant, amount, actor)
+    }
 
-        settle_grant(&mut grant, env.ledger().timestamp())?;
+    /// Grantee-only. Registers, or with None removes, the ed25519 public key whose signatures
+    /// may authorize withdraw_signed on the grantee's grants.
+    pub fn set_withdraw_signer(
+        env: Env,
+        grantee: Address,
+        public_key: Option<BytesN<32>>,
+    ) -> Result<(), Error> {
+        grantee.require_auth();
+        let key = DataKey::WithdrawSigner(grantee);
+        match public_key {
+            Some(public_key) => write_record(&env, &key, &public_key),
+            None => remove_record(&env, &key),
+        }
+        Ok(())
+    }
 
-        if amount > grant.claimable {
+    /// Anyone may call, e.g. a relayer paying the fees. Withdraws `amount` for the grantee
+    /// on the strength of an ed25519 signature by the grantee's registered signer over the
+    /// XDR encoding of `(contract address, grant_id, amount, nonce)`. `nonce` must be above
+    /// the last one the grantee used, so each signature works once. The nonce and the amount
+    /// are checked first and fail with contract errors; only an invalid signature aborts the
+    /// call. Grants withdrawn by the admin cannot be withdrawn this way.
+    pub fn withdraw_signed(
+        env: Env,
+        grant_id: u64,
+        amount: i128,
+        signature: BytesN<64>,
+        nonce: u64,
+    ) -> Result<(), Error> {
+        if amount <= 0 {
             return Err(Error::InvalidAmount);
         }
 
-        grant.claimable = grant
-            .claimable
-            .checked_sub(amount)
-            .ok_or(Error::MathOverflow)?;
-        grant.withdrawn = grant
-            .withdrawn
-            .checked_add(amount)
-            .ok_or(Error::MathOverflow)?;
+        let grant = read_grant(&env, grant_id)?;
+        let grantee = grant.recipient.clone();
+        if withdraw_authorizer(&env, grant_id, &grant)? != grantee {
+            return Err(Error::NotAuthorized);
+        }
+        let public_key: BytesN<32> = read_record(&env, &DataKey::WithdrawSigner(grantee.clone()))
+            .ok_or(Error::NotAuthorized)?;
+        let nonce_key = DataKey::WithdrawNonce(grantee.clone());
+        let last_nonce: Option<u64> = read_record(&env, &nonce_key);
+        if last_nonce.is_some_and(|last| nonce <= last) {
+            return Err(Error::InvalidNonce);
+        }
+        // A bad signature traps the host, so everything that can fail with a contract error
+        // is checked before it.
+        dry_run_withdraw(&env, grant_id, amount)?;
 
-        let accounted = grant
-            .withdrawn
-            .checked_add(grant.claimable)
-            .ok_or(Error::MathOverflow)?;
+        let message = (env.current_contract_address(), grant_id, amount, nonce).to_xdr(&env);
+        env.crypto()
+            .ed25519_verify(&public_key, &message, &signature);
+        write_record(&env, &nonce_key, &nonce);
 
-        if accounted > grant.total_amount {
-            return Err(Error::InvalidState);
-        }
+        withdraw_from(&env, grant_id, grant, amount, grantee)?;
+        Ok(())
+    }
+
+    /// Read-only. Last nonce the grantee used with withdraw_signed, if any.
+    pub fn get_withdraw_nonce(env: Env, grantee: Address) -> Option<u64> {
+        read_record(&env, &DataKey::WithdrawNonce(grantee))
+    }
 
-        if grant.withdrawn == grant.total_amount {
-            grant.status = GrantStatus::Completed;
+    /// Grantee-only. Withdraws the full claimable balance from each listed grant paid to
+    /// `grantee`, under a single authorization, and returns the total withdrawn. Ids that are
+    /// missing, belong to someone else, are admin-withdrawn, or have nothing withdrawable right
+    /// now (including rate-limited ones) are skipped. At most MAX_PAGE_SIZE ids per call.
+    pub fn withdraw_all_grants(
+        env: Env,
+        grantee: Address,
+        grant_ids: Vec<u64>,
+    ) -> Result<i128, Error> {
+        if grant_ids.len() > MAX_PAGE_SIZE {
+            return Err(Error::BatchTooLarge);
+        }
+        grantee.require_auth();
+
+        let mut total: i128 = 0;
+        for grant_id in grant_ids.iter() {
+            let grant = match read_grant(&env, grant_id) {
+                Ok(grant) => grant,
+                Err(_) => continue,
+            };
+            if withdraw_authorizer(&env, grant_id, &grant)? != grantee {
+                continue;
+            }
+            let amount = preview_grant_at_now(&env, &grant)?.claimable;
+            if amount <= 0 {
+                continue;
+            }
+            match withdraw_from(&env, grant_id, grant, amount, grantee.clone()) {
+                Ok(_) => total = total.checked_add(amount).ok_or(Error::MathOverflow)?,
+                Err(Error::RateLimited) => continue,
+                Err(err) => return Err(err),
+            }
         }
+        Ok(total)
+    }
 
-        grant.last_claim_time = env.ledger().timestamp();
-        write_grant(&env, grant_id, &grant);
-        Ok(())
+    /// Read-only. Returns the `withdrawn` total and whether the grant would complete if
+    /// `withdraw(grant_id, amount)` ran now, surfacing the same errors without mutating state.
+    pub fn simulate_withdraw(env: Env, grant_id: u64, amount: i128) -> Result<(i128, bool), Error> {
+        let grant = dry_run_withdraw(&env, grant_id, amount)?;
+        Ok((grant.withdrawn, grant.status == GrantStatus::Completed))
+    }
+
+    /// Read-only, no auth. Like simulate_withdraw, but reports a failure inside the returned
+    /// struct so callers can check a withdrawal before collecting signatures.
+    pub fn preview_withdraw(env: Env, grant_id: u64, amount: i128) -> WithdrawPreview {
+        match dry_run_withdraw(&env, grant_id, amount) {
+            Ok(grant) => WithdrawPreview {
+                error: None,
+                amount,
+                withdrawn: grant.withdrawn,
+                completes: grant.status == GrantStatus::Completed,
+            },
+            Err(err) => WithdrawPreview {
+                error: Some(err as u32),
+                amount: 0,
+                withdrawn: 0,
+                completes: false,
+            },
+        }
     }
 
     /// Anyone may call. Cancel an active grant if the grantee has not claimed in 90+ days; return remaining funds to treasury.
     pub fn slash_inactive_grant(env: Env, grant_id: u64) -> Result<(), Error> {
+        ensure_not_paused(&env, "slash_inactive_grant")?;
         let mut grant = read_grant(&env, grant_id)?;
 
         if grant.status != GrantStatus::Active {
@@ -347,10 +3681,10 @@ impl GrantContract {
         }
 
         let now = env.ledger().timestamp();
-        settle_grant(&mut grant, now)?;
+        settle_grant(&mut grant, now, read_dust_threshold(&env))?;
 
         if grant.status != GrantStatus::Active {
-            write_grant(&env, grant_id, &grant);
+            write_grant(&env, grant_id, &grant)?;
             return Err(Error::InvalidState);
         }
 
@@ -364,23 +3698,69 @@ impl GrantContract {
             .checked_sub(grant.withdrawn)
             .ok_or(Error::MathOverflow)?;
 
+        grant.claimable = 0;
         grant.flow_rate = 0;
         grant.status = GrantStatus::Cancelled;
-        write_grant(&env, grant_id, &grant);
+        write_grant(&env, grant_id, &grant)?;
+        on_grant_cancelled(&env, grant_id)?;
+        record_history(
+            &env,
+            grant_id,
+            HistoryAction::Slashed,
+            env.current_contract_address(),
+            Some(remaining),
+        );
 
-        if remaining > 0 {
-            let contract = env.current_contract_address();
-            let token = read_grant_token(&env)?;
-            let treasury = read_treasury(&env)?;
-            let client = token::Client::new(&env, &token);
-            client.transfer(&contract, &treasury, remaining);
-        }
+        refund_to_treasury(&env, remaining)?;
 
         Ok(())
     }
 
+    /// Recipient-only. Turns down a grant nothing has been withdrawn from yet; the grant is
+    /// cancelled and its full budget is owed back to the admin, who collects it with
+    /// claim_refund. A vesting grant can only be declined before anything has vested.
+    pub fn decline_grant(env: Env, grant_id: u64) -> Result<(), Error> {
+        let admin = read_admin(&env)?;
+        ensure_not_paused(&env, "decline_grant")?;
+        if let Some(schedule) = read_vesting(&env, grant_id) {
+            let recipient = schedule.recipient.clone();
+            recipient.require_auth();
+            if schedule.claimed != 0 || vested_amount(&schedule, vesting_now(&env, &schedule))? != 0
+            {
+                return Err(Error::InvalidState);
+            }
+            let unvested =
+                stop_vesting(&env, recipient, grant_id, schedule, HistoryAction::Declined)?;
+            return owe_refund_to(&env, grant_id, unvested, &admin);
+        }
+
+        let mut grant = read_grant(&env, grant_id)?;
+        grant.recipient.require_auth();
+
+        if grant.status != GrantStatus::Active || grant.withdrawn != 0 {
+            return Err(Error::InvalidState);
+        }
+
+        grant.last_update_ts = env.ledger().timestamp();
+        grant.claimable = 0;
+        grant.flow_rate = 0;
+        grant.status = GrantStatus::Cancelled;
+        write_grant(&env, grant_id, &grant)?;
+        on_grant_cancelled(&env, grant_id)?;
+        record_history(
+            &env,
+            grant_id,
+            HistoryAction::Declined,
+            grant.recipient.clone(),
+            Some(grant.total_amount),
+        );
+
+        owe_refund_to(&env, grant_id, grant.total_amount, &admin)
+    }
+
     pub fn update_rate(env: Env, grant_id: u64, new_rate: i128) -> Result<(), Error> {
-        require_admin_auth(&env)?;
+        let admin = require_admin_auth(&env)?;
+        ensure_terms_unlocked(&env, grant_id)?;
 
         if new_rate < 0 {
             return Err(Error::InvalidRate);
@@ -393,17 +3773,77 @@ impl GrantContract {
 
         let old_rate = grant.flow_rate;
 
-        settle_grant(&mut grant, env.ledger().timestamp())?;
+        settle_grant(&mut grant, env.ledger().timestamp(), read_dust_threshold(&env))?;
 
         if grant.status != GrantStatus::Active {
-            write_grant(&env, grant_id, &grant);
+            write_grant(&env, grant_id, &grant)?;
             return Err(Error::InvalidState);
         }
 
         grant.flow_rate = new_rate;
         grant.rate_updated_at = grant.last_update_ts;
 
-        write_grant(&env, grant_id, &grant);
+        write_grant(&env, grant_id, &grant)?;
+        record_history(
+            &env,
+            grant_id,
+            HistoryAction::RateUpdated,
+            admin,
+            Some(new_rate),
+        );
+
+        env.events().publish(
+            (symbol_short!("rateupdt"), grant_id),
+            (old_rate, new_rate, grant.rate_updated_at),
+        );
+
+        Ok(())
+    }
+
+    /// Admin-only. Re-times the unstreamed remainder so the grant finishes at `new_end`.
+    /// Already accrued and withdrawn amounts are settled first and never reduced.
+    pub fn reschedule_grant(env: Env, grant_id: u64, new_end: u64) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        ensure_terms_unlocked(&env, grant_id)?;
+
+        let mut grant = read_grant(&env, grant_id)?;
+        if grant.status != GrantStatus::Active {
+            return Err(Error::InvalidState);
+        }
+
+        let now = env.ledger().timestamp();
+        if new_end <= now {
+            return Err(Error::InvalidRate);
+        }
+
+        let old_rate = grant.flow_rate;
+        settle_grant(&mut grant, now, read_dust_threshold(&env))?;
+
+        if grant.status != GrantStatus::Active {
+            write_grant(&env, grant_id, &grant)?;
+            return Err(Error::InvalidState);
+        }
+
+        // Settling keeps withdrawn + claimable within total_amount.
+        let remaining = grant.total_amount - grant.withdrawn - grant.claimable;
+
+        // Round up so the remainder is fully streamed by new_end.
+        let duration = i128::from(new_end - now);
+        let new_rate = remaining
+            .checked_add(duration - 1)
+            .ok_or(Error::MathOverflow)?
+            / duration;
+
+        grant.flow_rate = new_rate;
+        grant.rate_updated_at = now;
+        write_grant(&env, grant_id, &grant)?;
+        record_history(
+            &env,
+            grant_id,
+            HistoryAction::Rescheduled,
+            admin,
+            Some(new_rate),
+        );
 
         env.events().publish(
             (symbol_short!("rateupdt"), grant_id),
@@ -413,7 +3853,47 @@ impl GrantContract {
         Ok(())
     }
 
-    /// Rescue stray tokens sent directly to the contract. Admin-only. Ensures contract_balance - amount >= total_allocated_funds for the grant token.
+    /// Admin-only. Moves an active vesting grant to vest over `new_duration` from `new_start`,
+    /// keeping its amount, cliff and curve. Fails with InvalidAmount if the new schedule would
+    /// have vested less by now than the recipient has already claimed, so a claim is never
+    /// undone. Schedules that are paused or made of tranches cannot be rescheduled.
+    pub fn reschedule_vesting(
+        env: Env,
+        grant_id: u64,
+        new_start: u64,
+        new_duration: u64,
+    ) -> Result<(), Error> {
+        let admin = require_admin_auth(&env)?;
+        ensure_terms_unlocked(&env, grant_id)?;
+        let mut schedule =
+            read_vesting(&env, grant_id).ok_or_else(|| missing_grant_error(&env, grant_id))?;
+        if schedule.status != GrantStatus::Active
+            || schedule.paused_at.is_some()
+            || !schedule.tranches.is_empty()
+        {
+            return Err(Error::InvalidState);
+        }
+
+        schedule.start = new_start;
+        schedule.duration = new_duration;
+        vested_amount(&schedule, schedule.start)?;
+        check_start_offset(&env, &schedule)?;
+        if vested_amount(&schedule, vesting_now(&env, &schedule))? < schedule.claimed {
+            return Err(Error::InvalidAmount);
+        }
+
+        write_vesting(&env, grant_id, &schedule)?;
+        record_history(
+            &env,
+            grant_id,
+            HistoryAction::Rescheduled,
+            admin,
+            Some(i128::from(new_duration)),
+        );
+        Ok(())
+    }
+
+    /// Rescue stray tokens sent directly to the contract. Admin-only. Ensures contract_balance - amount >= allocated_funds for the grant token, plus any bonuses escrowed in the token.
     pub fn rescue_tokens(
         env: Env,
         token_address: Address,
@@ -421,6 +3901,7 @@ impl GrantContract {
         to: Address,
     ) -> Result<(), Error> {
         require_admin_auth(&env)?;
+        ensure_not_paused(&env, "rescue_tokens")?;
 
         if amount <= 0 {
             return Err(Error::InvalidAmount);
@@ -430,11 +3911,17 @@ impl GrantContract {
         let client = token::Client::new(&env, &token_address);
         let contract_balance = client.balance(&contract);
 
-        let total_allocated = if token_address == read_grant_token(&env)? {
-            total_allocated_funds(&env)?
+        let grant_allocated = if token_address == read_grant_token(&env)? {
+            allocated_funds(&env)?
         } else {
             0
         };
+        let total_allocated = grant_allocated
+            .checked_add(read_total(
+                &env,
+                &DataKey::PendingBonuses(token_address.clone()),
+            ))
+            .ok_or(Error::MathOverflow)?;
 
         let after_rescue = contract_balance
             .checked_sub(amount)
@@ -443,7 +3930,7 @@ impl GrantContract {
             return Err(Error::RescueWouldViolateAllocated);
         }
 
-        client.transfer(&contract, &to, amount);
+        client.transfer(&contract, &to, &amount);
         Ok(())
     }
 }
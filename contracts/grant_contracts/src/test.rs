@@ -1,9 +1,31 @@
 #![cfg(test)]
 
-use super::{Error, GrantContract, GrantContractClient, GrantStatus};
+extern crate std;
+
+use super::testutils::create_mock_token;
+use super::vesting::{
+    bps_of, compute_claimable_balance, compute_claimable_balance_rounded,
+    compute_claimable_balance_with_cliff, compute_claimable_curve,
+    compute_claimable_excluding_pauses, compute_claimable_stepped, mul_div_floor, preview_schedule,
+    split_bps, time_to_vest, try_compute_claimable_balance,
+    try_compute_claimable_balance_with_cliff, validate_bps, validate_bps_total, BpsError,
+    ClaimError, ClaimState, Portion, Rounding, Schedule, ScheduleError, Segment, VestingCurve,
+    VestingError, BPS_DENOMINATOR, MAX_PREVIEW_POINTS,
+};
+use super::{
+    grant_record_keys, AdminAction, AdminMultisig, ClaimableDetails, ClaimableReason, DataKey,
+    Error, Grant, GrantContract, GrantContractClient, GrantExport, GrantRecord, GrantStatus,
+    HistoryAction, PortionData, RoundingData, ScheduleSegmentData, TimeBasis, TokenStats,
+    VestingCurveData, VestingScheduleData, VestingTranche, WithdrawLimit, WithdrawPreview,
+    WithdrawReceipt, CONTRACT_VERSION, SCHEMA_VERSION,
+};
+use ed25519_dalek::{Signer, SigningKey};
 use soroban_sdk::{
-    testutils::{Address as _, AuthorizedFunction, Ledger},
-    Address, Env, InvokeError,
+    contract, contractimpl, symbol_short,
+    testutils::{Address as _, AuthorizedFunction, Events, Ledger, Logs},
+    token, vec,
+    xdr::ToXdr,
+    Address, BytesN, Env, IntoVal, InvokeError, Val,
 };
 
 fn set_timestamp(env: &Env, timestamp: u64) {
@@ -12,6 +34,106 @@ fn set_timestamp(env: &Env, timestamp: u64) {
     });
 }
 
+fn set_sequence(env: &Env, sequence_number: u32) {
+    env.ledger().with_mut(|li| {
+        li.sequence_number = sequence_number;
+    });
+}
+
+/// Records the last `on_withdraw` callback it receives.
+#[contract]
+pub struct MockHook;
+
+#[contractimpl]
+impl MockHook {
+    pub fn on_withdraw(env: Env, grant_id: u64, amount: i128) {
+        env.storage()
+            .instance()
+            .set(&symbol_short!("last"), &(grant_id, amount));
+    }
+
+    pub fn last(env: Env) -> Option<(u64, i128)> {
+        env.storage().instance().get(&symbol_short!("last"))
+    }
+}
+
+/// Quotes a fixed price of 1.5 reference units per asset unit.
+#[contract]
+pub struct MockOracle;
+
+#[contractimpl]
+impl MockOracle {
+    pub fn price(_env: Env, _asset: Address, _reference: Address) -> i128 {
+        15_000_000
+    }
+}
+
+/// Registers a Stellar asset contract with no balances, for tests that never move tokens.
+fn register_token(env: &Env) -> Address {
+    env.register_stellar_asset_contract(Address::generate(env))
+}
+
+/// Registers a Stellar asset contract and mints `amount` of it to `to`.
+fn create_token(env: &Env, admin: &Address, to: &Address, amount: i128) -> Address {
+    let token = env.register_stellar_asset_contract(admin.clone());
+    token::StellarAssetClient::new(env, &token)
+        .mock_all_auths()
+        .mint(to, &amount);
+    token
+}
+
+fn count_events(env: &Env, topic: soroban_sdk::Symbol, grant_id: u64) -> u32 {
+    let topics: soroban_sdk::Vec<soroban_sdk::Val> = (topic, grant_id).into_val(env);
+    let mut count = 0;
+    for (_, event_topics, _) in env.events().all().iter() {
+        if event_topics == topics {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Data of the last event the contract published under `(topic, grant_id)`.
+fn last_event_data(env: &Env, topic: soroban_sdk::Symbol, grant_id: u64) -> Val {
+    let topics: soroban_sdk::Vec<Val> = (topic, grant_id).into_val(env);
+    let mut data = None;
+    for (_, event_topics, event_data) in env.events().all().iter() {
+        if event_topics == topics {
+            data = Some(event_data);
+        }
+    }
+    data.unwrap()
+}
+
+/// Set in the child process assert_aborts spawns.
+const ABORT_CHILD_VAR: &str = "GRANT_CONTRACTS_ABORT_CHILD";
+
+/// Asserts that `body` aborts the process. A contract call that traps in the host, such as
+/// withdraw_signed on a bad signature, panics inside the SDK's non-unwinding entry wrapper, so
+/// even a `try_` call cannot catch it in-process. The test therefore re-runs itself, by its
+/// harness name `test_name`, in a child process that executes `body`.
+fn assert_aborts(test_name: &str, body: impl FnOnce()) {
+    if std::env::var_os(ABORT_CHILD_VAR).is_some() {
+        body();
+        return;
+    }
+    let status = std::process::Command::new(std::env::current_exe().unwrap())
+        .args([test_name, "--exact", "--test-threads=1"])
+        .env(ABORT_CHILD_VAR, "1")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        // SIGABRT, rather than an ordinary test failure in the setup.
+        assert_eq!(status.signal(), Some(6), "{test_name} did not abort");
+    }
+    #[cfg(not(unix))]
+    assert!(!status.success(), "{test_name} did not fail");
+}
+
 fn assert_contract_error<T, C>(
     result: Result<Result<T, C>, Result<Error, InvokeError>>,
     expected: Error,
@@ -24,11 +146,11 @@ fn test_update_rate_settles_before_changing_rate() {
     let env = Env::default();
     let admin = Address::generate(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
     let treasury = Address::generate(&env);
 
     let contract_id = env.register_contract(None, GrantContract);
     let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
 
     let grant_id: u64 = 1;
     let rate_1: i128 = 10;
@@ -66,7 +188,7 @@ fn test_update_rate_requires_admin_auth() {
     let env = Env::default();
     let admin = Address::generate(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
+    let grant_token = register_token(&env);
     let treasury = Address::generate(&env);
 
     let contract_id = env.register_contract(None, GrantContract);
@@ -96,7 +218,7 @@ fn test_update_rate_immediately_after_creation() {
     let env = Env::default();
     let admin = Address::generate(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
+    let grant_token = register_token(&env);
     let treasury = Address::generate(&env);
 
     let contract_id = env.register_contract(None, GrantContract);
@@ -126,7 +248,7 @@ fn test_update_rate_multiple_times_with_time_gaps() {
     let env = Env::default();
     let admin = Address::generate(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
+    let grant_token = register_token(&env);
     let treasury = Address::generate(&env);
 
     let contract_id = env.register_contract(None, GrantContract);
@@ -155,7 +277,7 @@ fn test_update_rate_pause_then_resume() {
     let env = Env::default();
     let admin = Address::generate(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
+    let grant_token = register_token(&env);
     let treasury = Address::generate(&env);
 
     let contract_id = env.register_contract(None, GrantContract);
@@ -187,11 +309,11 @@ fn test_update_rate_rejects_invalid_rate_and_inactive_states() {
     let env = Env::default();
     let admin = Address::generate(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
     let treasury = Address::generate(&env);
 
     let contract_id = env.register_contract(None, GrantContract);
     let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 2_000);
 
     set_timestamp(&env, 0);
     client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
@@ -242,11 +364,11 @@ fn test_withdraw_after_rate_updates_no_extra_withdrawal() {
     let env = Env::default();
     let admin = Address::generate(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
     let treasury = Address::generate(&env);
 
     let contract_id = env.register_contract(None, GrantContract);
     let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
 
     let grant_id: u64 = 9;
 
@@ -267,7 +389,7 @@ fn test_withdraw_after_rate_updates_no_extra_withdrawal() {
 
     assert_contract_error(
         client.mock_all_auths().try_withdraw(&grant_id, &1),
-        Error::InvalidAmount,
+        Error::InsufficientReleasedBalance,
     );
 
     set_timestamp(&env, 180);
@@ -282,7 +404,7 @@ fn test_withdraw_after_rate_updates_no_extra_withdrawal() {
 
     assert_contract_error(
         client.mock_all_auths().try_withdraw(&grant_id, &1),
-        Error::InvalidAmount,
+        Error::InsufficientReleasedBalance,
     );
 }
 
@@ -290,23 +412,31 @@ fn test_withdraw_after_rate_updates_no_extra_withdrawal() {
 fn test_rescue_tokens_requires_admin_auth() {
     let env = Env::default();
     let admin = Address::generate(&env);
-    let grant_token = Address::generate(&env);
     let treasury = Address::generate(&env);
     let to = Address::generate(&env);
 
     let contract_id = env.register_contract(None, GrantContract);
     let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 100);
 
-    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
-    // Calling rescue_tokens without admin auth must fail (auth or NotAuthorized).
-    assert!(client.try_rescue_tokens(&grant_token, &100, &to).is_err());
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    // The rescue is authorized by the admin and nobody else.
+    client
+        .mock_all_auths()
+        .rescue_tokens(&grant_token, &100, &to);
+    let auths = env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, admin);
+    assert_eq!(token::Client::new(&env, &grant_token).balance(&to), 100);
 }
 
 #[test]
 fn test_rescue_tokens_rejects_invalid_amount() {
     let env = Env::default();
     let admin = Address::generate(&env);
-    let grant_token = Address::generate(&env);
+    let grant_token = register_token(&env);
     let treasury = Address::generate(&env);
     let to = Address::generate(&env);
 
@@ -333,7 +463,7 @@ fn test_slash_inactive_grant_reverts_if_less_than_90_days() {
     let env = Env::default();
     let admin = Address::generate(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
+    let grant_token = register_token(&env);
     let treasury = Address::generate(&env);
 
     let contract_id = env.register_contract(None, GrantContract);
@@ -344,7 +474,7 @@ fn test_slash_inactive_grant_reverts_if_less_than_90_days() {
     client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
     client
         .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &5_000, &10);
+        .create_grant(&grant_id, &recipient, &100_000_000, &10);
 
     // 89 days later (less than 90) – slash should revert
     const SECS_89_DAYS: u64 = 89 * 24 * 60 * 60;
@@ -363,11 +493,11 @@ fn test_slash_inactive_grant_reverts_if_not_active() {
     let env = Env::default();
     let admin = Address::generate(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
     let treasury = Address::generate(&env);
 
     let contract_id = env.register_contract(None, GrantContract);
     let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
 
     let grant_id: u64 = 11;
     set_timestamp(&env, 1_000);
@@ -388,18 +518,18 @@ fn test_slash_inactive_grant_updates_last_claim_time_on_withdraw() {
     let env = Env::default();
     let admin = Address::generate(&env);
     let recipient = Address::generate(&env);
-    let grant_token = Address::generate(&env);
     let treasury = Address::generate(&env);
 
     let contract_id = env.register_contract(None, GrantContract);
     let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
 
     let grant_id: u64 = 12;
     set_timestamp(&env, 1_000);
     client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
     client
         .mock_all_auths()
-        .create_grant(&grant_id, &recipient, &10_000, &10);
+        .create_grant(&grant_id, &recipient, &100_000_000, &10);
 
     set_timestamp(&env, 1_100);
     client.mock_all_auths().withdraw(&grant_id, &1_000);
@@ -412,3 +542,5228 @@ fn test_slash_inactive_grant_updates_last_claim_time_on_withdraw() {
         Error::GrantNotInactive,
     );
 }
+
+#[test]
+fn test_get_grants_by_admin_lists_grants_per_admin() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let other_admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let other_contract_id = env.register_contract(None, GrantContract);
+    let other_client = GrantContractClient::new(&env, &other_contract_id);
+
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    other_client
+        .mock_all_auths()
+        .initialize(&other_admin, &grant_token, &treasury);
+
+    for grant_id in [20_u64, 21, 22] {
+        client
+            .mock_all_auths()
+            .create_grant(&grant_id, &recipient, &1_000, &1);
+    }
+    other_client
+        .mock_all_auths()
+        .create_grant(&30, &recipient, &1_000, &1);
+
+    assert_eq!(
+        client.get_grants_by_admin(&admin, &0, &10),
+        vec![&env, 20_u64, 21, 22]
+    );
+    assert_eq!(client.get_grants_by_admin(&other_admin, &0, &10).len(), 0);
+    assert_eq!(
+        other_client.get_grants_by_admin(&other_admin, &0, &10),
+        vec![&env, 30_u64]
+    );
+
+    // Pagination
+    assert_eq!(
+        client.get_grants_by_admin(&admin, &1, &1),
+        vec![&env, 21_u64]
+    );
+    assert_eq!(client.get_grants_by_admin(&admin, &5, &10).len(), 0);
+}
+
+#[test]
+fn test_get_grants_by_admin_keeps_cancelled_unless_pruning_enabled() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 3_000);
+
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    for grant_id in [40_u64, 41, 42] {
+        client
+            .mock_all_auths()
+            .create_grant(&grant_id, &recipient, &1_000, &1);
+    }
+
+    client.mock_all_auths().cancel_grant(&40);
+    assert_eq!(
+        client.get_grants_by_admin(&admin, &0, &10),
+        vec![&env, 40_u64, 41, 42]
+    );
+
+    client.mock_all_auths().set_prune_cancelled(&true);
+    client.mock_all_auths().cancel_grant(&41);
+    assert_eq!(
+        client.get_grants_by_admin(&admin, &0, &10),
+        vec![&env, 40_u64, 42]
+    );
+}
+
+#[test]
+fn test_get_grants_by_grantee_moves_on_recipient_change() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&50, &alice, &1_000, &1);
+    client
+        .mock_all_auths()
+        .create_grant(&51, &alice, &1_000, &1);
+    client.mock_all_auths().create_grant(&52, &bob, &1_000, &1);
+
+    assert_eq!(
+        client.get_grants_by_grantee(&alice, &0, &10),
+        vec![&env, 50_u64, 51]
+    );
+    assert_eq!(
+        client.get_grants_by_grantee(&bob, &0, &10),
+        vec![&env, 52_u64]
+    );
+
+    client.mock_all_auths().update_recipient(&50, &bob);
+
+    assert_eq!(
+        client.get_grants_by_grantee(&alice, &0, &10),
+        vec![&env, 51_u64]
+    );
+    assert_eq!(
+        client.get_grants_by_grantee(&bob, &0, &10),
+        vec![&env, 52_u64, 50]
+    );
+    assert_eq!(client.get_grant(&50).recipient, bob);
+}
+
+#[test]
+fn test_get_grants_by_grantee_paginates() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    // Sixty grants in instance storage exceed the default per-invocation budget.
+    env.budget().reset_unlimited();
+    for grant_id in 0_u64..60 {
+        client
+            .mock_all_auths()
+            .create_grant(&grant_id, &recipient, &1_000, &1);
+    }
+
+    // Page size is capped at 50 regardless of the requested limit.
+    let first_page = client.get_grants_by_grantee(&recipient, &0, &100);
+    assert_eq!(first_page.len(), 50);
+    assert_eq!(first_page.get(0), Some(0));
+
+    let second_page = client.get_grants_by_grantee(&recipient, &50, &50);
+    assert_eq!(second_page.len(), 10);
+    assert_eq!(second_page.get(9), Some(59));
+}
+
+#[test]
+fn test_simulate_withdraw_matches_real_withdraw() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    let grant_id: u64 = 60;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+
+    set_timestamp(&env, 40);
+    assert_eq!(client.simulate_withdraw(&grant_id, &300), (300, false));
+    // Simulation leaves state untouched.
+    assert_eq!(client.get_grant(&grant_id).withdrawn, 0);
+
+    client.mock_all_auths().withdraw(&grant_id, &300);
+    let grant = client.get_grant(&grant_id);
+    assert_eq!(grant.withdrawn, 300);
+    assert_eq!(grant.status, GrantStatus::Active);
+
+    set_timestamp(&env, 100);
+    assert_contract_error(
+        client.try_simulate_withdraw(&grant_id, &701),
+        Error::InsufficientReleasedBalance,
+    );
+    assert_eq!(client.simulate_withdraw(&grant_id, &700), (1_000, true));
+
+    client.mock_all_auths().withdraw(&grant_id, &700);
+    let grant = client.get_grant(&grant_id);
+    assert_eq!(grant.withdrawn, 1_000);
+    assert_eq!(grant.status, GrantStatus::Completed);
+}
+
+#[test]
+fn test_missing_grant_returns_grant_not_found() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+
+    let missing: u64 = 404;
+    assert_contract_error(client.try_get_grant(&missing), Error::GrantNotFound);
+    assert_contract_error(client.try_claimable(&missing), Error::GrantNotFound);
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&missing, &1),
+        Error::GrantNotFound,
+    );
+    assert_contract_error(
+        client.try_simulate_withdraw(&missing, &1),
+        Error::GrantNotFound,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_cancel_grant(&missing),
+        Error::GrantNotFound,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_update_rate(&missing, &1),
+        Error::GrantNotFound,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_update_recipient(&missing, &recipient),
+        Error::GrantNotFound,
+    );
+    assert_contract_error(
+        client.try_slash_inactive_grant(&missing),
+        Error::GrantNotFound,
+    );
+}
+
+#[test]
+fn test_calls_before_initialize_return_not_initialized() {
+    let env = Env::default();
+    let recipient = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant(&1, &recipient, &1_000, &1),
+        Error::NotInitialized,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_set_prune_cancelled(&true),
+        Error::NotInitialized,
+    );
+}
+
+#[test]
+fn test_dust_threshold_completes_grant_short_of_total() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+
+    // Strict by default: one unit short stays Active.
+    client
+        .mock_all_auths()
+        .create_grant(&70, &recipient, &100, &33);
+    set_timestamp(&env, 3);
+    client.mock_all_auths().update_rate(&70, &0);
+    let strict = client.get_grant(&70);
+    assert_eq!(strict.claimable, 99);
+    assert_eq!(strict.status, GrantStatus::Active);
+
+    client.mock_all_auths().set_dust_threshold(&2);
+    client
+        .mock_all_auths()
+        .create_grant(&71, &recipient, &100, &33);
+    set_timestamp(&env, 6);
+    client.mock_all_auths().withdraw(&71, &99);
+
+    let grant = client.get_grant(&71);
+    assert_eq!(grant.withdrawn, 99);
+    assert_eq!(grant.status, GrantStatus::Completed);
+
+    assert_contract_error(
+        client.mock_all_auths().try_set_dust_threshold(&-1),
+        Error::InvalidAmount,
+    );
+}
+
+#[test]
+fn test_grant_exists_and_get_grant_status() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 200);
+
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+
+    assert!(!client.grant_exists(&80));
+    assert_contract_error(client.try_get_grant_status(&80), Error::GrantNotFound);
+
+    client
+        .mock_all_auths()
+        .create_grant(&80, &recipient, &100, &10);
+    assert!(client.grant_exists(&80));
+    assert_eq!(client.get_grant_status(&80), GrantStatus::Active);
+
+    set_timestamp(&env, 10);
+    assert_eq!(client.get_grant_status(&80), GrantStatus::Completed);
+
+    client
+        .mock_all_auths()
+        .create_grant(&81, &recipient, &100, &1);
+    client.mock_all_auths().cancel_grant(&81);
+    assert_eq!(client.get_grant_status(&81), GrantStatus::Cancelled);
+}
+
+#[test]
+fn test_completion_event_fires_once() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    let grant_id: u64 = 90;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &100, &10);
+
+    set_timestamp(&env, 5);
+    client.mock_all_auths().withdraw(&grant_id, &50);
+    assert_eq!(count_events(&env, symbol_short!("completed"), grant_id), 0);
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().withdraw(&grant_id, &40);
+    let data: (u64, i128, i128, u64) =
+        last_event_data(&env, symbol_short!("completed"), grant_id).into_val(&env);
+    assert_eq!(data, (grant_id, 100, 100, 10));
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw(&grant_id, &10);
+
+    assert_eq!(count_events(&env, symbol_short!("completed"), grant_id), 1);
+}
+
+#[test]
+fn test_vesting_completion_publishes_event() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 100);
+
+    let grant_id: u64 = 409;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &100, &0, &10, &0);
+
+    set_timestamp(&env, 5);
+    client.mock_all_auths().claim_vested(&grant_id);
+    assert_eq!(count_events(&env, symbol_short!("completed"), grant_id), 0);
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().claim_vested(&grant_id);
+    assert_eq!(count_events(&env, symbol_short!("completed"), grant_id), 1);
+    let data: (u64, i128, i128, u64) =
+        last_event_data(&env, symbol_short!("completed"), grant_id).into_val(&env);
+    assert_eq!(data, (grant_id, 100, 100, 10));
+    assert_eq!(client.get_completed_count(&recipient), 1);
+}
+
+#[test]
+fn test_get_progress_rounds_bps_down() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    let grant_id: u64 = 100;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &3, &1);
+
+    let progress = client.get_progress(&grant_id);
+    assert_eq!(progress.total_amount, 3);
+    assert_eq!(progress.released_amount, 0);
+    assert_eq!(progress.released_bps, 0);
+
+    set_timestamp(&env, 1);
+    assert_eq!(client.get_progress(&grant_id).released_bps, 3_333);
+
+    set_timestamp(&env, 2);
+    client.mock_all_auths().withdraw(&grant_id, &1);
+    let progress = client.get_progress(&grant_id);
+    assert_eq!(progress.released_amount, 2);
+    assert_eq!(progress.withdrawn_amount, 1);
+    assert_eq!(progress.released_bps, 6_666);
+
+    set_timestamp(&env, 3);
+    assert_eq!(client.get_progress(&grant_id).released_bps, 10_000);
+}
+
+#[test]
+fn test_ratio_bps_handles_extreme_amounts() {
+    assert_eq!(super::ratio_bps(1, 0), 0);
+    assert_eq!(super::ratio_bps(i128::MAX, i128::MAX), 10_000);
+    assert_eq!(super::ratio_bps(i128::MAX / 2, i128::MAX), 5_000);
+}
+
+#[test]
+fn test_reschedule_grant_shortens_duration_monotonically() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    let grant_id: u64 = 110;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &1);
+
+    set_timestamp(&env, 200);
+    client.mock_all_auths().withdraw(&grant_id, &150);
+    assert_eq!(client.claimable(&grant_id), 50);
+
+    // Originally ends at t=1_000; pull the end in to t=400.
+    client.mock_all_auths().reschedule_grant(&grant_id, &400);
+    let grant = client.get_grant(&grant_id);
+    assert_eq!(grant.flow_rate, 4);
+    assert_eq!(grant.withdrawn, 150);
+    assert_eq!(grant.claimable, 50);
+
+    let mut last = client.claimable(&grant_id);
+    for ts in [250_u64, 300, 399, 400, 500] {
+        set_timestamp(&env, ts);
+        let now_claimable = client.claimable(&grant_id);
+        assert!(now_claimable >= last);
+        last = now_claimable;
+    }
+    assert_eq!(last, 850);
+    assert_eq!(client.get_grant_status(&grant_id), GrantStatus::Completed);
+}
+
+#[test]
+fn test_reschedule_grant_rejects_past_end_and_finished_grants() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+
+    set_timestamp(&env, 100);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&111, &recipient, &1_000, &1);
+    assert_contract_error(
+        client.mock_all_auths().try_reschedule_grant(&111, &100),
+        Error::InvalidRate,
+    );
+
+    client.mock_all_auths().cancel_grant(&111);
+    assert_contract_error(
+        client.mock_all_auths().try_reschedule_grant(&111, &500),
+        Error::InvalidState,
+    );
+}
+
+#[test]
+fn test_reschedule_vesting_shortens_duration_monotonically() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+
+    let grant_id: u64 = 406;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &1_000, &0, &1_000, &0);
+
+    set_timestamp(&env, 400);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 400);
+
+    // Stretching to 2_000 would vest only 200 by now, below the 400 already claimed.
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_reschedule_vesting(&grant_id, &0, &2_000),
+        Error::InvalidAmount,
+    );
+
+    // Originally ends at t=1_000; pull the end in to t=500.
+    client
+        .mock_all_auths()
+        .reschedule_vesting(&grant_id, &0, &500);
+    let schedule = client.get_vesting_schedule(&grant_id);
+    assert_eq!((schedule.start, schedule.duration), (0, 500));
+    assert_eq!(schedule.claimed, 400);
+
+    let mut last = client.get_claimable(&grant_id);
+    assert_eq!(last, 400);
+    for ts in [450_u64, 499, 500, 600] {
+        set_timestamp(&env, ts);
+        let now_claimable = client.get_claimable(&grant_id);
+        assert!(now_claimable >= last);
+        last = now_claimable;
+    }
+    assert_eq!(last, 600);
+
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 600);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_reschedule_vesting(&grant_id, &0, &1_000),
+        Error::InvalidState,
+    );
+}
+
+#[test]
+fn test_token_stats_track_escrow_release_and_withdrawal() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let other_token = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_500);
+
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&120, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .create_grant(&121, &recipient, &500, &5);
+
+    assert_eq!(
+        client.get_token_stats(&grant_token),
+        TokenStats {
+            escrowed: 1_500,
+            released: 0,
+            withdrawn: 0,
+        }
+    );
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw(&120, &150);
+    // Grant 121 has accrued 100 but nothing has settled it yet.
+    assert_eq!(
+        client.get_token_stats(&grant_token),
+        TokenStats {
+            escrowed: 1_350,
+            released: 200,
+            withdrawn: 150,
+        }
+    );
+
+    // Cancelling settles 121 (100 released); only that vested 100 stays escrowed.
+    client.mock_all_auths().cancel_grant(&121);
+    assert_eq!(
+        client.get_token_stats(&grant_token),
+        TokenStats {
+            escrowed: 950,
+            released: 300,
+            withdrawn: 150,
+        }
+    );
+
+    assert_eq!(client.get_token_stats(&other_token), TokenStats::default());
+}
+
+#[test]
+fn test_token_stats_include_vesting_grants() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&400, &recipient, &1_000, &0, &100, &0);
+    assert_eq!(
+        client.get_token_stats(&grant_token),
+        TokenStats {
+            escrowed: 1_000,
+            released: 0,
+            withdrawn: 0,
+        }
+    );
+
+    set_timestamp(&env, 40);
+    client.mock_all_auths().claim_vested(&400);
+    assert_eq!(
+        client.get_token_stats(&grant_token),
+        TokenStats {
+            escrowed: 600,
+            released: 400,
+            withdrawn: 400,
+        }
+    );
+
+    // Revoking fixes the entitlement at the 500 vested so far.
+    set_timestamp(&env, 50);
+    client.mock_all_auths().revoke_vesting(&400);
+    assert_eq!(
+        client.get_token_stats(&grant_token),
+        TokenStats {
+            escrowed: 100,
+            released: 500,
+            withdrawn: 400,
+        }
+    );
+
+    set_timestamp(&env, 100);
+    client.mock_all_auths().claim_vested(&400);
+    assert_eq!(
+        client.get_token_stats(&grant_token),
+        TokenStats {
+            escrowed: 0,
+            released: 500,
+            withdrawn: 500,
+        }
+    );
+}
+
+#[test]
+fn test_create_grant_auto_assigns_sequential_ids() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+
+    assert_eq!(
+        client
+            .mock_all_auths()
+            .create_grant_auto(&recipient, &1_000, &1),
+        1
+    );
+    assert_eq!(
+        client
+            .mock_all_auths()
+            .create_grant_auto(&recipient, &2_000, &2),
+        2
+    );
+
+    // Explicit ids keep working and are skipped by the counter.
+    client
+        .mock_all_auths()
+        .create_grant(&3, &recipient, &3_000, &3);
+    assert_eq!(
+        client
+            .mock_all_auths()
+            .create_grant_auto(&recipient, &4_000, &4),
+        4
+    );
+
+    assert_eq!(client.get_grant(&1).total_amount, 1_000);
+    assert_eq!(client.get_grant(&2).total_amount, 2_000);
+    assert_eq!(client.get_grant(&3).total_amount, 3_000);
+    assert_eq!(client.get_grant(&4).total_amount, 4_000);
+}
+
+#[test]
+fn test_decline_grant_refunds_admin() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 2_000);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let grant_id: u64 = 130;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &1);
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().decline_grant(&grant_id);
+
+    let auths = env.auths();
+    assert_eq!(auths[0].0, recipient);
+
+    let grant = client.get_grant(&grant_id);
+    assert_eq!(grant.status, GrantStatus::Cancelled);
+    assert_eq!(grant.claimable, 0);
+    // The refund waits for claim_refund, like a cancellation's.
+    assert_eq!(client.get_refund_owed(&grant_id), 1_000);
+    assert_eq!(token_client.balance(&admin), 0);
+
+    assert_eq!(client.mock_all_auths().claim_refund(&grant_id), 1_000);
+    assert_eq!(token_client.balance(&admin), 1_000);
+    assert_eq!(token_client.balance(&treasury), 0);
+    assert_eq!(client.get_refund_owed(&grant_id), 0);
+
+    // A vesting grant can be declined until something vests.
+    client
+        .mock_all_auths()
+        .create_grant_vested(&407, &recipient, &1_000, &100, &100, &0);
+    client.mock_all_auths().decline_grant(&407);
+    assert_eq!(env.auths()[0].0, recipient);
+    let schedule = client.get_vesting_schedule(&407);
+    assert_eq!(schedule.status, GrantStatus::Cancelled);
+    assert_eq!(client.get_refund_owed(&407), 1_000);
+    set_timestamp(&env, 150);
+    assert_eq!(client.get_claimable(&407), 0);
+    assert_eq!(client.mock_all_auths().claim_refund(&407), 1_000);
+    assert_eq!(token_client.balance(&admin), 2_000);
+    assert_eq!(token_client.balance(&contract_id), 0);
+}
+
+#[test]
+fn test_decline_vesting_grant_rejects_once_vested() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&408, &recipient, &1_000, &0, &100, &0);
+
+    set_timestamp(&env, 10);
+    assert_contract_error(
+        client.mock_all_auths().try_decline_grant(&408),
+        Error::InvalidState,
+    );
+    assert_eq!(
+        client.get_vesting_schedule(&408).status,
+        GrantStatus::Active
+    );
+}
+
+#[test]
+fn test_decline_grant_rejects_after_withdrawal() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    let grant_id: u64 = 131;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &1);
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().withdraw(&grant_id, &5);
+    assert_contract_error(
+        client.mock_all_auths().try_decline_grant(&grant_id),
+        Error::InvalidState,
+    );
+}
+
+#[test]
+fn test_withdraw_notifies_grant_hook() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+    let hook_id = env.register_contract(None, MockHook);
+    let hook_client = MockHookClient::new(&env, &hook_id);
+
+    let grant_id: u64 = 140;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .set_grant_hook(&grant_id, &Some(hook_id.clone()));
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().withdraw(&grant_id, &75);
+    assert_eq!(hook_client.last(), Some((grant_id, 75)));
+
+    // A hook without the callback does not block withdrawals.
+    client
+        .mock_all_auths()
+        .set_grant_hook(&grant_id, &Some(grant_token.clone()));
+    client.mock_all_auths().withdraw(&grant_id, &5);
+    assert_eq!(client.get_grant(&grant_id).withdrawn, 80);
+
+    client.mock_all_auths().set_grant_hook(&grant_id, &None);
+    client.mock_all_auths().withdraw(&grant_id, &5);
+    assert_eq!(hook_client.last(), Some((grant_id, 75)));
+}
+
+#[test]
+fn test_history_records_actions_in_order() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let new_recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+
+    let grant_id: u64 = 150;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().withdraw(&grant_id, &60);
+    set_timestamp(&env, 20);
+    client.mock_all_auths().update_rate(&grant_id, &5);
+    set_timestamp(&env, 30);
+    client
+        .mock_all_auths()
+        .update_recipient(&grant_id, &new_recipient);
+    set_timestamp(&env, 40);
+    client.mock_all_auths().cancel_grant(&grant_id);
+
+    let history = client.get_history(&grant_id, &0, &10);
+    assert_eq!(history.len(), 5);
+
+    let expected = [
+        (HistoryAction::Created, admin.clone(), 0, Some(1_000)),
+        (HistoryAction::Withdrawn, recipient.clone(), 10, Some(60)),
+        (HistoryAction::RateUpdated, admin.clone(), 20, Some(5)),
+        (HistoryAction::RecipientChanged, admin.clone(), 30, None),
+        (HistoryAction::Cancelled, admin.clone(), 40, None),
+    ];
+    for (i, (action, actor, timestamp, amount)) in expected.into_iter().enumerate() {
+        let entry = history.get(i as u32).unwrap();
+        assert_eq!(entry.action, action);
+        assert_eq!(entry.actor, actor);
+        assert_eq!(entry.timestamp, timestamp);
+        assert_eq!(entry.amount, amount);
+    }
+
+    let page = client.get_history(&grant_id, &3, &10);
+    assert_eq!(page.len(), 2);
+    assert_eq!(page.get(0).unwrap().action, HistoryAction::RecipientChanged);
+}
+
+#[test]
+fn test_history_truncates_oldest_entries_at_limit() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    let grant_id: u64 = 151;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client.mock_all_auths().set_history_limit(&3);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+
+    for (ts, amount) in [(1_u64, 1_i128), (2, 2), (3, 3), (4, 4)] {
+        set_timestamp(&env, ts);
+        client.mock_all_auths().withdraw(&grant_id, &amount);
+    }
+
+    let history = client.get_history(&grant_id, &0, &10);
+    assert_eq!(history.len(), 3);
+    assert_eq!(history.get(0).unwrap().amount, Some(2));
+    assert_eq!(history.get(2).unwrap().amount, Some(4));
+    for entry in history.iter() {
+        assert_eq!(entry.action, HistoryAction::Withdrawn);
+    }
+
+    assert_contract_error(
+        client.mock_all_auths().try_set_history_limit(&0),
+        Error::InvalidAmount,
+    );
+}
+
+#[test]
+fn test_export_grant_round_trips_state_and_settings() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let new_recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+    let hook_id = env.register_contract(None, MockHook);
+
+    let grant_id: u64 = 160;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+
+    let export = client.export_grant(&grant_id);
+    assert_eq!(export.history.len(), 1);
+    assert!(export.hook.is_empty());
+    assert_eq!(export.history_limit, 50);
+    assert_eq!(export.dust_threshold, 0);
+    assert!(!export.prune_cancelled);
+
+    client.mock_all_auths().set_history_limit(&10);
+    client.mock_all_auths().set_dust_threshold(&3);
+    client.mock_all_auths().set_prune_cancelled(&true);
+    client
+        .mock_all_auths()
+        .set_grant_hook(&grant_id, &Some(hook_id));
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().withdraw(&grant_id, &40);
+    set_timestamp(&env, 20);
+    client.mock_all_auths().update_rate(&grant_id, &20);
+    client
+        .mock_all_auths()
+        .update_recipient(&grant_id, &new_recipient);
+    set_timestamp(&env, 30);
+
+    let export = client.export_grant(&grant_id);
+    let grant = client.get_grant(&grant_id);
+    assert_eq!(export.grant_id, grant_id);
+    let GrantRecord::Streaming(exported) = export.grant else {
+        panic!("streaming grant exported as vesting");
+    };
+    assert_eq!(exported.recipient, new_recipient);
+    assert_eq!(exported.total_amount, grant.total_amount);
+    assert_eq!(exported.withdrawn, 40);
+    assert_eq!(exported.claimable, grant.claimable);
+    assert_eq!(exported.claimable, 360);
+    assert_eq!(exported.flow_rate, 20);
+    assert_eq!(exported.rate_updated_at, 20);
+    assert_eq!(exported.last_claim_time, 10);
+    assert_eq!(exported.status, GrantStatus::Active);
+    assert_eq!(export.history.len(), 5);
+    assert_eq!(export.hook.len(), 1);
+    assert_eq!(export.history_limit, 10);
+    assert_eq!(export.dust_threshold, 3);
+    assert!(export.prune_cancelled);
+
+    assert_contract_error(client.try_export_grant(&999), Error::GrantNotFound);
+}
+
+#[test]
+fn test_cancel_grant_keeps_vested_and_refunds_remainder() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let grant_id: u64 = 170;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw(&grant_id, &100);
+
+    // Half of the grant has streamed when it is cancelled.
+    set_timestamp(&env, 50);
+    client.mock_all_auths().cancel_grant(&grant_id);
+
+    let grant = client.get_grant(&grant_id);
+    assert_eq!(grant.status, GrantStatus::Cancelled);
+    assert_eq!(grant.claimable, 400);
+    assert_eq!(client.mock_all_auths().claim_refund(&grant_id), 500);
+    assert_eq!(token_client.balance(&treasury), 500);
+    assert_eq!(token_client.balance(&recipient), 100);
+    assert_eq!(token_client.balance(&contract_id), 400);
+
+    // Nothing more accrues, but the vested balance can still be withdrawn.
+    set_timestamp(&env, 100);
+    assert_eq!(client.claimable(&grant_id), 400);
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&grant_id, &401),
+        Error::InsufficientReleasedBalance,
+    );
+    client.mock_all_auths().withdraw(&grant_id, &400);
+    assert_eq!(token_client.balance(&recipient), 500);
+    assert_eq!(token_client.balance(&contract_id), 0);
+
+    let grant = client.get_grant(&grant_id);
+    assert_eq!(grant.withdrawn, 500);
+    assert_eq!(grant.claimable, 0);
+    assert_eq!(grant.status, GrantStatus::Cancelled);
+    assert_eq!(
+        client.get_token_stats(&grant_token),
+        TokenStats {
+            escrowed: 0,
+            released: 500,
+            withdrawn: 500,
+        }
+    );
+}
+
+#[test]
+fn test_preview_withdraw_matches_real_withdraw() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    let grant_id: u64 = 180;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+
+    set_timestamp(&env, 40);
+    let preview = client.preview_withdraw(&grant_id, &250);
+    assert_eq!(
+        preview,
+        WithdrawPreview {
+            error: None,
+            amount: 250,
+            withdrawn: 250,
+            completes: false,
+        }
+    );
+    assert!(env.auths().is_empty());
+    client.mock_all_auths().withdraw(&grant_id, &250);
+    let grant = client.get_grant(&grant_id);
+    assert_eq!(grant.withdrawn, preview.withdrawn);
+    assert_eq!(grant.status, GrantStatus::Active);
+
+    assert_eq!(
+        client.preview_withdraw(&grant_id, &151).error,
+        Some(Error::InsufficientReleasedBalance as u32)
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&grant_id, &151),
+        Error::InsufficientReleasedBalance,
+    );
+    assert_eq!(
+        client.preview_withdraw(&999, &1).error,
+        Some(Error::GrantNotFound as u32)
+    );
+
+    set_timestamp(&env, 100);
+    let preview = client.preview_withdraw(&grant_id, &750);
+    assert_eq!(preview.error, None);
+    assert!(preview.completes);
+    client.mock_all_auths().withdraw(&grant_id, &750);
+    let grant = client.get_grant(&grant_id);
+    assert_eq!(grant.withdrawn, preview.withdrawn);
+    assert_eq!(grant.status, GrantStatus::Completed);
+}
+
+#[test]
+fn test_version_and_schema_version() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    assert_eq!(client.version(), CONTRACT_VERSION);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    assert_eq!(client.version(), (0, 1, 0));
+    assert_eq!(client.schema_version(), SCHEMA_VERSION);
+    assert_eq!(client.schema_version(), 2);
+}
+
+#[test]
+fn test_crate_version_meta_matches_contractmeta_encoding() {
+    use soroban_sdk::xdr::{Limits, ScMetaEntry, ScMetaV0, WriteXdr};
+
+    let entry = ScMetaEntry::ScMetaV0(ScMetaV0 {
+        key: "crate_version".try_into().unwrap(),
+        val: env!("CARGO_PKG_VERSION").try_into().unwrap(),
+    });
+    assert_eq!(
+        entry.to_xdr(Limits::none()).unwrap(),
+        crate::CRATE_VERSION_META.to_vec()
+    );
+}
+
+#[test]
+fn test_get_claimable_clamps_to_contract_balance() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 300);
+
+    let grant_id: u64 = 190;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+
+    assert_eq!(
+        client.get_claimable_details(&grant_id),
+        ClaimableDetails {
+            amount: 0,
+            accrued: 0,
+            contract_balance: 300,
+            reason: ClaimableReason::NothingAccrued,
+        }
+    );
+
+    set_timestamp(&env, 20);
+    assert_eq!(client.get_claimable(&grant_id), 200);
+    assert_eq!(
+        client.get_claimable_details(&grant_id).reason,
+        ClaimableReason::Available
+    );
+
+    set_timestamp(&env, 50);
+    assert_eq!(client.claimable(&grant_id), 500);
+    assert_eq!(
+        client.get_claimable_details(&grant_id),
+        ClaimableDetails {
+            amount: 300,
+            accrued: 500,
+            contract_balance: 300,
+            reason: ClaimableReason::Underfunded,
+        }
+    );
+
+    client
+        .mock_all_auths()
+        .set_admin_multisig(&vec![&env, admin.clone()], &1);
+    client
+        .mock_all_auths()
+        .execute_admin_action(&admin, &AdminAction::SetPaused(true));
+    assert_eq!(client.get_claimable(&grant_id), 0);
+    assert_eq!(
+        client.get_claimable_details(&grant_id),
+        ClaimableDetails {
+            amount: 0,
+            accrued: 500,
+            contract_balance: 300,
+            reason: ClaimableReason::Paused,
+        }
+    );
+
+    let exempt = vec![&env, soroban_sdk::Symbol::new(&env, "withdraw")];
+    client
+        .mock_all_auths()
+        .execute_admin_action(&admin, &AdminAction::SetPauseExemptions(exempt));
+    assert_eq!(
+        client.get_claimable_details(&grant_id).reason,
+        ClaimableReason::Underfunded
+    );
+}
+
+#[test]
+fn test_get_grants_by_status_follows_transitions() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 3_000);
+
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    for grant_id in [200_u64, 201, 202] {
+        client
+            .mock_all_auths()
+            .create_grant(&grant_id, &recipient, &1_000, &10);
+    }
+    assert_eq!(
+        client.get_grants_by_status(&GrantStatus::Active, &0, &10),
+        vec![&env, 200_u64, 201, 202]
+    );
+
+    set_timestamp(&env, 50);
+    client.mock_all_auths().cancel_grant(&201);
+    set_timestamp(&env, 100);
+    client.mock_all_auths().withdraw(&202, &1_000);
+
+    assert_eq!(
+        client.get_grants_by_status(&GrantStatus::Active, &0, &10),
+        vec![&env, 200_u64]
+    );
+    assert_eq!(
+        client.get_grants_by_status(&GrantStatus::Cancelled, &0, &10),
+        vec![&env, 201_u64]
+    );
+    assert_eq!(
+        client.get_grants_by_status(&GrantStatus::Completed, &0, &10),
+        vec![&env, 202_u64]
+    );
+
+    // Withdrawing the vested balance of a cancelled grant does not move it.
+    client.mock_all_auths().withdraw(&201, &500);
+    assert_eq!(
+        client.get_grants_by_status(&GrantStatus::Cancelled, &0, &10),
+        vec![&env, 201_u64]
+    );
+}
+
+#[test]
+fn test_cancel_grant_idempotent_reports_second_call() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let grant_id: u64 = 210;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+
+    set_timestamp(&env, 30);
+    assert!(client.mock_all_auths().cancel_grant_idempotent(&grant_id));
+    assert!(!client.mock_all_auths().cancel_grant_idempotent(&grant_id));
+
+    assert_eq!(client.get_grant_status(&grant_id), GrantStatus::Cancelled);
+    assert_eq!(client.mock_all_auths().claim_refund(&grant_id), 700);
+    assert_eq!(token_client.balance(&treasury), 700);
+    assert_eq!(client.get_history(&grant_id, &0, &10).len(), 3);
+    assert_contract_error(
+        client.mock_all_auths().try_cancel_grant(&grant_id),
+        Error::InvalidState,
+    );
+}
+
+/// Asserts `grant_id` appears in exactly the `expected` status list.
+fn assert_listed_only_under(client: &GrantContractClient, grant_id: u64, expected: GrantStatus) {
+    for status in [
+        GrantStatus::Active,
+        GrantStatus::Completed,
+        GrantStatus::Cancelled,
+    ] {
+        let listed = client
+            .get_grants_by_status(&status, &0, &50)
+            .contains(grant_id);
+        assert_eq!(listed, status == expected);
+    }
+}
+
+#[test]
+fn test_get_grants_by_status_lists_each_grant_once() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 2_000);
+
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&220, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .create_grant(&221, &recipient, &1_000, &10);
+    assert_listed_only_under(&client, 220, GrantStatus::Active);
+    assert_listed_only_under(&client, 221, GrantStatus::Active);
+
+    set_timestamp(&env, 40);
+    client.mock_all_auths().update_rate(&220, &20);
+    client.mock_all_auths().cancel_grant(&221);
+    assert_listed_only_under(&client, 220, GrantStatus::Active);
+    assert_listed_only_under(&client, 221, GrantStatus::Cancelled);
+
+    // Fully streamed by t=70, but only listed as Completed once something settles it.
+    set_timestamp(&env, 80);
+    assert_listed_only_under(&client, 220, GrantStatus::Active);
+    client.mock_all_auths().withdraw(&220, &1_000);
+    assert_listed_only_under(&client, 220, GrantStatus::Completed);
+
+    for grant_id in 222_u64..225 {
+        client
+            .mock_all_auths()
+            .create_grant(&grant_id, &recipient, &1_000, &10);
+    }
+    assert_eq!(
+        client.get_grants_by_status(&GrantStatus::Active, &1, &2),
+        vec![&env, 223_u64, 224]
+    );
+    assert_eq!(
+        client
+            .get_grants_by_status(&GrantStatus::Active, &3, &2)
+            .len(),
+        0
+    );
+}
+
+#[test]
+fn test_withdraw_limit_caps_each_period() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    let grant_id: u64 = 230;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &10_000, &10);
+    client.mock_all_auths().set_withdraw_limit(
+        &grant_id,
+        &Some(WithdrawLimit {
+            max_per_period: 300,
+            period: 100,
+        }),
+    );
+
+    set_timestamp(&env, 50);
+    client.mock_all_auths().withdraw(&grant_id, &200);
+    set_timestamp(&env, 60);
+    client.mock_all_auths().withdraw(&grant_id, &100);
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&grant_id, &1),
+        Error::RateLimited,
+    );
+    assert_eq!(
+        client.preview_withdraw(&grant_id, &1).error,
+        Some(Error::RateLimited as u32)
+    );
+
+    // The window opened at t=50, so it rolls at t=150.
+    set_timestamp(&env, 149);
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&grant_id, &1),
+        Error::RateLimited,
+    );
+    set_timestamp(&env, 150);
+    client.mock_all_auths().withdraw(&grant_id, &300);
+    assert_eq!(client.get_grant(&grant_id).withdrawn, 600);
+
+    client.mock_all_auths().set_withdraw_limit(&grant_id, &None);
+    client.mock_all_auths().withdraw(&grant_id, &500);
+    assert_eq!(client.get_grant(&grant_id).withdrawn, 1_100);
+
+    assert_contract_error(
+        client.mock_all_auths().try_set_withdraw_limit(
+            &grant_id,
+            &Some(WithdrawLimit {
+                max_per_period: 300,
+                period: 0,
+            }),
+        ),
+        Error::InvalidAmount,
+    );
+}
+
+#[test]
+fn test_grant_tags_index_and_cap() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    for grant_id in [240_u64, 241] {
+        client
+            .mock_all_auths()
+            .create_grant(&grant_id, &recipient, &1_000, &10);
+    }
+
+    let infra = symbol_short!("infra");
+    let research = symbol_short!("research");
+    let edu = symbol_short!("edu");
+    client
+        .mock_all_auths()
+        .set_grant_tags(&240, &vec![&env, infra.clone(), research.clone()]);
+    client
+        .mock_all_auths()
+        .set_grant_tags(&241, &vec![&env, infra.clone(), edu.clone(), edu.clone()]);
+
+    assert_eq!(
+        client.get_grants_by_tag(&infra, &0, &10),
+        vec![&env, 240_u64, 241]
+    );
+    assert_eq!(
+        client.get_grants_by_tag(&research, &0, &10),
+        vec![&env, 240_u64]
+    );
+    assert_eq!(client.get_grants_by_tag(&edu, &0, &10), vec![&env, 241_u64]);
+    assert_eq!(
+        client.get_grant_tags(&241),
+        vec![&env, infra.clone(), edu.clone()]
+    );
+
+    // Retagging moves the grant between tag indexes.
+    client
+        .mock_all_auths()
+        .set_grant_tags(&240, &vec![&env, edu.clone()]);
+    assert_eq!(
+        client.get_grants_by_tag(&infra, &0, &10),
+        vec![&env, 241_u64]
+    );
+    assert_eq!(client.get_grants_by_tag(&research, &0, &10).len(), 0);
+    assert_eq!(
+        client.get_grants_by_tag(&edu, &0, &10),
+        vec![&env, 241_u64, 240]
+    );
+
+    let too_many = vec![
+        &env,
+        symbol_short!("a"),
+        symbol_short!("b"),
+        symbol_short!("c"),
+        symbol_short!("d"),
+        symbol_short!("e"),
+        symbol_short!("f"),
+    ];
+    assert_contract_error(
+        client.mock_all_auths().try_set_grant_tags(&240, &too_many),
+        Error::TooManyTags,
+    );
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().withdraw(&240, &50);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_set_grant_tags(&240, &vec![&env, infra]),
+        Error::InvalidState,
+    );
+}
+
+#[test]
+fn test_get_grants_batch_returns_none_for_missing_ids() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&250, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .create_grant(&252, &recipient, &500, &5);
+
+    set_timestamp(&env, 10);
+    let grants = client.get_grants(&vec![&env, 252_u64, 251, 250, 999]);
+    assert_eq!(grants.len(), 4);
+    assert_eq!(grants.get(0).unwrap().unwrap().total_amount, 500);
+    assert!(grants.get(1).unwrap().is_none());
+    let first = grants.get(2).unwrap().unwrap();
+    assert_eq!(first.total_amount, 1_000);
+    assert_eq!(first.claimable, 100);
+    assert!(grants.get(3).unwrap().is_none());
+
+    assert_eq!(client.get_grants(&vec![&env]).len(), 0);
+
+    let mut too_many = vec![&env];
+    for grant_id in 0_u64..51 {
+        too_many.push_back(grant_id);
+    }
+    assert_contract_error(client.try_get_grants(&too_many), Error::BatchTooLarge);
+}
+
+#[test]
+fn test_check_invariants_flags_corrupted_grant() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 2_000);
+
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&260, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .create_grant(&261, &recipient, &1_000, &10);
+    assert!(client.check_invariants(&260));
+
+    set_timestamp(&env, 30);
+    client.mock_all_auths().withdraw(&260, &200);
+    client.mock_all_auths().cancel_grant(&261);
+    assert!(client.check_invariants(&260));
+    assert!(client.check_invariants(&261));
+
+    set_timestamp(&env, 100);
+    client.mock_all_auths().withdraw(&260, &800);
+    assert_eq!(client.get_grant_status(&260), GrantStatus::Completed);
+    assert!(client.check_invariants(&260));
+
+    // Withdrawn more than the grant's total.
+    env.as_contract(&contract_id, || {
+        let key = DataKey::Grant(260);
+        let mut grant: Grant = env.storage().persistent().get(&key).unwrap();
+        grant.withdrawn = 1_500;
+        env.storage().persistent().set(&key, &grant);
+    });
+    assert!(!client.check_invariants(&260));
+
+    assert_contract_error(client.try_check_invariants(&999), Error::GrantNotFound);
+}
+
+#[test]
+fn test_compute_claimable_balance_is_linear_and_exact() {
+    assert_eq!(compute_claimable_balance(1_000, 100, 50, 1_000), 0);
+    assert_eq!(compute_claimable_balance(1_000, 100, 100, 1_000), 0);
+    assert_eq!(compute_claimable_balance(1_000, 100, 600, 1_000), 500);
+    assert_eq!(compute_claimable_balance(1_000, 100, 1_100, 1_000), 1_000);
+    assert_eq!(
+        compute_claimable_balance(1_000, 100, u64::MAX, 1_000),
+        1_000
+    );
+    assert_eq!(compute_claimable_balance(1_000, 100, 100, 0), 1_000);
+    assert_eq!(compute_claimable_balance(10, 0, 1, 3), 3);
+
+    // No overflow at the extremes, and still exact.
+    let total = u128::MAX;
+    let duration = 315_360_000;
+    assert_eq!(
+        compute_claimable_balance(total, 0, duration / 2, duration),
+        total / 2
+    );
+    assert_eq!(
+        compute_claimable_balance(total, 0, duration - 1, duration),
+        total - total / u128::from(duration) - 1
+    );
+}
+
+#[test]
+fn test_cliff_vests_nothing_then_catches_up() {
+    let (total, start, cliff, duration) = (1_200_u128, 1_000_u64, 300_u64, 1_200_u64);
+    assert_eq!(
+        compute_claimable_balance_with_cliff(total, start, cliff, 1_299, duration),
+        0
+    );
+    assert_eq!(
+        compute_claimable_balance_with_cliff(total, start, cliff, 1_300, duration),
+        300
+    );
+
+    // A cliff past the end unlocks everything at the cliff.
+    assert_eq!(
+        compute_claimable_balance_with_cliff(total, start, 2_000, 2_999, duration),
+        0
+    );
+    assert_eq!(
+        compute_claimable_balance_with_cliff(total, start, 2_000, 3_000, duration),
+        total
+    );
+    assert_eq!(
+        compute_claimable_balance_with_cliff(total, u64::MAX - 10, 100, u64::MAX, duration),
+        0
+    );
+
+    for total in [0_u128, 1, 7, 1_000, 999_999_937, u128::MAX] {
+        for duration in [0_u64, 1, 13, 1_000, 315_360_000] {
+            for now in [0_u64, 999, 1_000, 1_001, 1_500, 2_000, 400_000_000] {
+                let linear = compute_claimable_balance(total, 1_000, now, duration);
+                // A zero cliff matches the linear schedule everywhere.
+                assert_eq!(
+                    compute_claimable_balance_with_cliff(total, 1_000, 0, now, duration),
+                    linear
+                );
+                // After the cliff, any cliff matches the linear schedule.
+                for cliff in [1_u64, 500, 1_000] {
+                    let with_cliff =
+                        compute_claimable_balance_with_cliff(total, 1_000, cliff, now, duration);
+                    if now >= 1_000 + cliff {
+                        assert_eq!(with_cliff, linear);
+                    } else {
+                        assert_eq!(with_cliff, 0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_try_compute_claimable_balance_reports_overflow() {
+    assert_eq!(
+        try_compute_claimable_balance(1_000, 100, 600, 1_000),
+        Ok(500)
+    );
+    // A zero duration is an instant vest, not an error.
+    assert_eq!(try_compute_claimable_balance(1_000, 100, 100, 0), Ok(1_000));
+
+    // The schedule end lies past u64::MAX, even though `now` is in range.
+    assert_eq!(
+        try_compute_claimable_balance(1_000, u64::MAX - 10, u64::MAX, 100),
+        Err(VestingError::Overflow)
+    );
+    assert_eq!(
+        compute_claimable_balance(1_000, u64::MAX - 10, u64::MAX, 100),
+        0
+    );
+    assert_eq!(
+        try_compute_claimable_balance_with_cliff(1_000, u64::MAX - 10, 100, u64::MAX, 5),
+        Err(VestingError::Overflow)
+    );
+    assert_eq!(
+        try_compute_claimable_balance_with_cliff(1_000, u64::MAX - 10, 5, 0, 100),
+        Err(VestingError::Overflow)
+    );
+    assert_eq!(
+        try_compute_claimable_balance_with_cliff(1_000, u64::MAX - 10, 5, u64::MAX, 10),
+        Ok(1_000)
+    );
+}
+
+#[test]
+fn test_create_grant_vested_rejects_unrepresentable_schedule() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+
+    client.mock_all_auths().set_max_start_offset(&None);
+    let start = u64::MAX - 10;
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant_vested(&320, &recipient, &1_000, &start, &100, &0),
+        Error::MathOverflow,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant_vested(&320, &recipient, &1_000, &start, &5, &100),
+        Error::MathOverflow,
+    );
+    assert_contract_error(client.try_get_vesting_schedule(&320), Error::GrantNotFound);
+
+    client
+        .mock_all_auths()
+        .create_grant_vested(&320, &recipient, &1_000, &start, &10, &5);
+}
+
+#[test]
+fn test_admin_withdraws_mode_requires_admin_auth() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    let grant_id: u64 = 270;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+
+    set_timestamp(&env, 10);
+    client.mock_all_auths().withdraw(&grant_id, &50);
+    assert_eq!(env.auths()[0].0, recipient);
+
+    client
+        .mock_all_auths()
+        .set_admin_withdraws(&grant_id, &true);
+    set_timestamp(&env, 20);
+    client.mock_all_auths().withdraw(&grant_id, &100);
+    let auths = env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, admin);
+
+    let history = client.get_history(&grant_id, &0, &10);
+    assert_eq!(history.get(1).unwrap().actor, recipient);
+    assert_eq!(history.get(2).unwrap().actor, admin);
+    assert_eq!(client.get_grant(&grant_id).withdrawn, 150);
+
+    client
+        .mock_all_auths()
+        .set_admin_withdraws(&grant_id, &false);
+    client.mock_all_auths().withdraw(&grant_id, &10);
+    assert_eq!(env.auths()[0].0, recipient);
+}
+
+#[test]
+fn test_get_value_in_reference_uses_grant_oracle() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let reference_token = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+    let oracle_id = env.register_contract(None, MockOracle);
+
+    let grant_id: u64 = 280;
+    set_timestamp(&env, 0);
+    client.mock_all_auths().initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+    assert_contract_error(
+        client.try_get_value_in_reference(&grant_id, &reference_token),
+        Error::OracleNotSet,
+    );
+
+    client
+        .mock_all_auths()
+        .set_grant_oracle(&grant_id, &Some(oracle_id.clone()));
+    assert_eq!(
+        client.get_value_in_reference(&grant_id, &reference_token),
+        1_500
+    );
+
+    set_timestamp(&env, 30);
+    client.mock_all_auths().withdraw(&grant_id, &201);
+    assert_eq!(
+        client.get_value_in_reference(&grant_id, &reference_token),
+        1_198
+    );
+
+    // Vesting grants are valued by what is still unclaimed.
+    client
+        .mock_all_auths()
+        .create_grant_vested(&411, &recipient, &1_000, &0, &100, &0);
+    client
+        .mock_all_auths()
+        .set_grant_oracle(&411, &Some(oracle_id));
+    assert_eq!(client.get_value_in_reference(&411, &reference_token), 1_500);
+    assert_eq!(client.mock_all_auths().claim_vested(&411), 300);
+    assert_eq!(client.get_value_in_reference(&411, &reference_token), 1_050);
+}
+
+#[test]
+fn test_stepped_vesting_unlocks_at_period_edges() {
+    let month = 2_592_000_u64;
+    let start = 1_000_u64;
+    assert_eq!(compute_claimable_stepped(1_200, start, 999, month, 12), 0);
+    assert_eq!(compute_claimable_stepped(1_200, start, start, month, 12), 0);
+    assert_eq!(
+        compute_claimable_stepped(1_200, start, start + month - 1, month, 12),
+        0
+    );
+    assert_eq!(
+        compute_claimable_stepped(1_200, start, start + month, month, 12),
+        100
+    );
+    assert_eq!(
+        compute_claimable_stepped(1_200, start, start + 3 * month + 1, month, 12),
+        300
+    );
+    assert_eq!(
+        compute_claimable_stepped(1_200, start, start + 12 * month, month, 12),
+        1_200
+    );
+    assert_eq!(
+        compute_claimable_stepped(1_200, start, u64::MAX, month, 12),
+        1_200
+    );
+
+    // The last step absorbs the rounding dust.
+    let total = 1_000_000_000_u128;
+    assert_eq!(compute_claimable_stepped(total, 0, 6, 1, 7), 857_142_852);
+    assert_eq!(compute_claimable_stepped(total, 0, 7, 1, 7), total);
+
+    // Degenerate schedules vest everything at start.
+    assert_eq!(compute_claimable_stepped(total, start, start - 1, 0, 7), 0);
+    assert_eq!(compute_claimable_stepped(total, start, start, 0, 7), total);
+    assert_eq!(
+        compute_claimable_stepped(total, start, start, month, 0),
+        total
+    );
+}
+
+#[test]
+fn test_schedule_sums_segments() {
+    let year = 31_536_000_u64;
+    // 10% at start, 60% linearly over two years, 30% at the end of year three.
+    let schedule = Schedule::new(&[
+        Segment {
+            start_offset: 0,
+            duration: 0,
+            portion: Portion::Bps(1_000),
+        },
+        Segment {
+            start_offset: 0,
+            duration: 2 * year,
+            portion: Portion::Bps(6_000),
+        },
+        Segment {
+            start_offset: 3 * year,
+            duration: 0,
+            portion: Portion::Bps(3_000),
+        },
+    ])
+    .unwrap();
+
+    let (total, start) = (1_000_000_u128, 5_000_u64);
+    let at = |now: u64| schedule.claimable_at(total, start, now).unwrap();
+    assert_eq!(at(start - 1), 0);
+    assert_eq!(at(start), 100_000);
+    assert_eq!(at(start + year / 2), 250_000);
+    assert_eq!(at(start + year), 400_000);
+    assert_eq!(at(start + 2 * year), 700_000);
+    assert_eq!(at(start + 3 * year - 1), 700_000);
+    assert_eq!(at(start + 3 * year), total);
+    assert_eq!(at(u64::MAX), total);
+
+    // Rounding dust lands in the last segment.
+    assert_eq!(schedule.claimable_at(7, start, start).unwrap(), 0);
+    assert_eq!(
+        schedule.claimable_at(7, start, start + 3 * year).unwrap(),
+        7
+    );
+
+    let amounts = Schedule::new(&[
+        Segment {
+            start_offset: 100,
+            duration: 100,
+            portion: Portion::Amount(400),
+        },
+        Segment {
+            start_offset: 200,
+            duration: 0,
+            portion: Portion::Amount(600),
+        },
+    ])
+    .unwrap();
+    assert_eq!(amounts.claimable_at(1_000, 0, 150).unwrap(), 200);
+    assert_eq!(amounts.claimable_at(1_000, 0, 200).unwrap(), 1_000);
+    assert_eq!(
+        amounts.claimable_at(999, 0, 150),
+        Err(ScheduleError::AmountMismatch)
+    );
+}
+
+#[test]
+fn test_linear_schedule_matches_compute_claimable_balance() {
+    for duration in [0_u64, 1, 13, 1_000] {
+        let schedule = Schedule::linear(duration);
+        for total in [0_u128, 7, 1_000_003, u128::MAX] {
+            for now in [0_u64, 99, 100, 101, 107, 600, 1_100, u64::MAX] {
+                assert_eq!(
+                    schedule.claimable_at(total, 100, now).unwrap(),
+                    compute_claimable_balance(total, 100, now, duration)
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_schedule_rejects_invalid_segments() {
+    let segment = |start_offset, duration, portion| Segment {
+        start_offset,
+        duration,
+        portion,
+    };
+    assert_eq!(Schedule::new(&[]), Err(ScheduleError::Empty));
+    assert_eq!(
+        Schedule::new(&[segment(0, 0, Portion::Bps(1_000)); 9]),
+        Err(ScheduleError::TooManySegments)
+    );
+    assert_eq!(
+        Schedule::new(&[
+            segment(0, 100, Portion::Bps(5_000)),
+            segment(99, 100, Portion::Bps(5_000)),
+        ]),
+        Err(ScheduleError::Overlapping)
+    );
+    assert_eq!(
+        Schedule::new(&[
+            segment(100, 0, Portion::Bps(5_000)),
+            segment(0, 0, Portion::Bps(5_000)),
+        ]),
+        Err(ScheduleError::Overlapping)
+    );
+    assert_eq!(
+        Schedule::new(&[
+            segment(0, 0, Portion::Bps(5_000)),
+            segment(0, 0, Portion::Amount(5_000)),
+        ]),
+        Err(ScheduleError::MixedPortions)
+    );
+    assert_eq!(
+        Schedule::new(&[segment(0, 10, Portion::Bps(9_999))]),
+        Err(ScheduleError::BpsNotFull)
+    );
+}
+
+#[test]
+fn test_claim_vested_transfers_vested_amount() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let grant_id: u64 = 290;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &1_000, &100, &1_000, &200);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant(&grant_id, &recipient, &1_000, &1),
+        Error::GrantAlreadyExists,
+    );
+
+    // Still inside the cliff at 15%.
+    set_timestamp(&env, 250);
+    assert_contract_error(
+        client.mock_all_auths().try_claim_vested(&grant_id),
+        Error::NothingToClaim,
+    );
+
+    set_timestamp(&env, 350);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 250);
+    assert_eq!(env.auths()[0].0, recipient);
+    assert_contract_error(
+        client.mock_all_auths().try_claim_vested(&grant_id),
+        Error::NothingToClaim,
+    );
+
+    set_timestamp(&env, 700);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 350);
+    assert_eq!(token_client.balance(&recipient), 600);
+
+    set_timestamp(&env, 1_200);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 400);
+    assert_eq!(token_client.balance(&recipient), 1_000);
+
+    let schedule = client.get_vesting_schedule(&grant_id);
+    assert_eq!(schedule.claimed, 1_000);
+    assert_eq!(schedule.status, GrantStatus::Completed);
+}
+
+#[test]
+fn test_cancel_vested_grant_stops_accrual() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let grant_id: u64 = 291;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &1_000, &0, &1_000, &0);
+
+    set_timestamp(&env, 200);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 200);
+    set_timestamp(&env, 400);
+    client.mock_all_auths().cancel_grant(&grant_id);
+    assert_eq!(client.mock_all_auths().claim_refund(&grant_id), 600);
+    assert_eq!(token_client.balance(&treasury), 600);
+    assert!(!client.mock_all_auths().cancel_grant_idempotent(&grant_id));
+
+    set_timestamp(&env, 900);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 200);
+    assert_eq!(token_client.balance(&recipient), 400);
+    assert_eq!(token_client.balance(&contract_id), 0);
+    assert_contract_error(
+        client.mock_all_auths().try_claim_vested(&grant_id),
+        Error::NothingToClaim,
+    );
+    assert_eq!(
+        client.get_vesting_schedule(&grant_id).status,
+        GrantStatus::Cancelled
+    );
+}
+
+#[test]
+fn test_archive_grant_hides_finished_grant_from_listings() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    for grant_id in [300_u64, 301, 302] {
+        client
+            .mock_all_auths()
+            .create_grant(&grant_id, &recipient, &1_000, &10);
+    }
+    client
+        .mock_all_auths()
+        .set_grant_tags(&301, &vec![&env, symbol_short!("infra")]);
+
+    assert_contract_error(
+        client.mock_all_auths().try_archive_grant(&301),
+        Error::InvalidState,
+    );
+
+    // Fully streamed but not yet withdrawn: still owed, so not archivable.
+    set_timestamp(&env, 100);
+    assert_contract_error(
+        client.mock_all_auths().try_archive_grant(&301),
+        Error::InvalidState,
+    );
+    client.mock_all_auths().withdraw(&301, &1_000);
+    client.mock_all_auths().archive_grant(&301);
+
+    assert!(client.is_archived(&301));
+    assert_eq!(
+        client.list_grants(&0, &10, &false),
+        vec![&env, 300_u64, 302]
+    );
+    assert_eq!(client.list_grants(&1, &10, &false), vec![&env, 302_u64]);
+    assert_eq!(
+        client.list_grants(&0, &10, &true),
+        vec![&env, 300_u64, 301, 302]
+    );
+    assert_eq!(
+        client.get_grants_by_admin(&admin, &0, &10),
+        vec![&env, 300_u64, 302]
+    );
+    assert_eq!(
+        client.get_grants_by_grantee(&recipient, &0, &10),
+        vec![&env, 300_u64, 302]
+    );
+    assert_eq!(
+        client
+            .get_grants_by_status(&GrantStatus::Completed, &0, &10)
+            .len(),
+        0
+    );
+    assert_eq!(
+        client
+            .get_grants_by_tag(&symbol_short!("infra"), &0, &10)
+            .len(),
+        0
+    );
+
+    // The grant's data is gone; only the tombstone remains.
+    assert_contract_error(client.try_get_grant(&301), Error::GrantArchived);
+    assert_contract_error(client.try_withdraw(&301, &1), Error::GrantArchived);
+    assert_contract_error(client.try_get_history(&301, &0, &10), Error::GrantArchived);
+    assert_contract_error(client.try_get_grant_age(&301), Error::GrantArchived);
+    assert_contract_error(client.try_is_funded(&301), Error::GrantArchived);
+    assert_eq!(client.get_tombstone(&301).status, GrantStatus::Completed);
+    assert_contract_error(client.try_get_tombstone(&300), Error::GrantNotFound);
+    assert_contract_error(client.try_get_grant(&999), Error::GrantNotFound);
+
+    // Archiving again is a no-op.
+    client.mock_all_auths().archive_grant(&301);
+}
+
+#[test]
+fn test_archive_grant_publishes_export_and_blocks_reuse() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    let grant_id: u64 = 376;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    // Completes 3 short of its total, which stays counted as escrowed until archived.
+    client.mock_all_auths().set_dust_threshold(&5);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_003, &10);
+    client
+        .mock_all_auths()
+        .set_grant_tags(&grant_id, &vec![&env, symbol_short!("infra")]);
+    let limit = WithdrawLimit {
+        max_per_period: 2_000,
+        period: 1_000,
+    };
+    client
+        .mock_all_auths()
+        .set_withdraw_limit(&grant_id, &Some(limit.clone()));
+    client.mock_all_auths().set_amount_scale(&grant_id, &2);
+    client.mock_all_auths().lock_terms(&grant_id);
+    set_timestamp(&env, 100);
+    client.mock_all_auths().withdraw(&grant_id, &1_000);
+    let export = client.export_grant(&grant_id);
+    assert_eq!(export.tags, vec![&env, symbol_short!("infra")]);
+    assert_eq!(export.withdraw_limit, vec![&env, limit]);
+    assert_eq!(export.withdraw_window.len(), 1);
+    assert_eq!(export.amount_scale, 2);
+    assert!(export.terms_locked);
+    assert_eq!(client.get_token_stats(&grant_token).escrowed, 3);
+
+    client.mock_all_auths().archive_grant(&grant_id);
+    assert_eq!(count_events(&env, symbol_short!("archived"), grant_id), 1);
+    let (_, _, data) = env.events().all().last().unwrap();
+    let published: GrantExport = data.into_val(&env);
+    assert_eq!(published.to_xdr(&env), export.clone().to_xdr(&env));
+
+    let tombstone = client.get_tombstone(&grant_id);
+    assert_eq!(
+        tombstone.export_hash,
+        env.crypto().sha256(&export.to_xdr(&env))
+    );
+    env.as_contract(&contract_id, || {
+        for key in grant_record_keys(grant_id) {
+            assert!(!env.storage().persistent().has(&key));
+            assert!(!env.storage().instance().has(&key));
+        }
+    });
+    assert_eq!(
+        client.get_token_stats(&grant_token),
+        TokenStats {
+            escrowed: 0,
+            released: 1_000,
+            withdrawn: 1_000,
+        }
+    );
+
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant(&grant_id, &recipient, &1_000, &10),
+        Error::GrantAlreadyExists,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant_vested(&grant_id, &recipient, &1_000, &0, &100, &0),
+        Error::GrantAlreadyExists,
+    );
+    assert_contract_error(client.try_get_grant(&grant_id), Error::GrantArchived);
+}
+
+#[test]
+fn test_grant_queries_handle_vesting_grants() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+
+    let grant_id: u64 = 401;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &1_000, &0, &100, &0);
+    assert!(client.grant_exists(&grant_id));
+    assert_eq!(client.get_grant_status(&grant_id), GrantStatus::Active);
+
+    set_timestamp(&env, 40);
+    assert_eq!(client.get_claimable(&grant_id), 400);
+    let progress = client.get_progress(&grant_id);
+    assert_eq!(progress.released_amount, 400);
+    assert_eq!(progress.withdrawn_amount, 0);
+    assert_eq!(progress.released_bps, 4_000);
+    assert!(client.check_invariants(&grant_id));
+    let GrantRecord::Vesting(exported) = client.export_grant(&grant_id).grant else {
+        panic!("vesting grant exported as streaming");
+    };
+    assert_eq!(exported, client.get_vesting_schedule(&grant_id));
+    assert_contract_error(
+        client.mock_all_auths().try_archive_grant(&grant_id),
+        Error::InvalidState,
+    );
+
+    set_timestamp(&env, 100);
+    client.mock_all_auths().claim_vested(&grant_id);
+    assert_eq!(client.get_grant_status(&grant_id), GrantStatus::Completed);
+    assert_eq!(client.get_claimable(&grant_id), 0);
+    assert!(client.check_invariants(&grant_id));
+
+    client.mock_all_auths().archive_grant(&grant_id);
+    assert!(!client.grant_exists(&grant_id));
+    assert_eq!(
+        client.get_tombstone(&grant_id).status,
+        GrantStatus::Completed
+    );
+    assert_contract_error(
+        client.try_get_vesting_schedule(&grant_id),
+        Error::GrantArchived,
+    );
+}
+
+#[test]
+fn test_completed_count_tracks_grants_completed_by_grantee() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let other = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&377, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .create_grant(&378, &recipient, &500, &10);
+    client
+        .mock_all_auths()
+        .create_grant(&379, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .create_grant(&380, &other, &1_000, &10);
+    assert_eq!(client.get_completed_count(&recipient), 0);
+
+    set_timestamp(&env, 100);
+    client.mock_all_auths().withdraw(&377, &1_000);
+    assert_eq!(client.get_completed_count(&recipient), 1);
+    client.mock_all_auths().withdraw(&378, &500);
+    assert_eq!(client.get_completed_count(&recipient), 2);
+
+    // Cancelled grants and grants held by someone else do not count.
+    client.mock_all_auths().cancel_grant(&379);
+    assert_eq!(client.get_completed_count(&recipient), 2);
+    assert_eq!(client.get_completed_count(&other), 0);
+}
+
+#[test]
+fn test_global_pause_requires_two_of_three_admins() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let signer_a = Address::generate(&env);
+    let signer_b = Address::generate(&env);
+    let signer_c = Address::generate(&env);
+    let outsider = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&381, &recipient, &1_000, &10);
+
+    let signers = vec![&env, signer_a.clone(), signer_b.clone(), signer_c.clone()];
+    assert_contract_error(
+        client.mock_all_auths().try_set_admin_multisig(&signers, &4),
+        Error::InvalidThreshold,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_set_admin_multisig(&vec![&env, signer_a.clone(), signer_a.clone()], &1),
+        Error::InvalidThreshold,
+    );
+    client.mock_all_auths().set_admin_multisig(&signers, &2);
+    assert_eq!(client.get_admin_multisig().unwrap().threshold, 2);
+
+    let pause = AdminAction::SetPaused(true);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_execute_admin_action(&outsider, &pause),
+        Error::NotAuthorized,
+    );
+    assert!(!client
+        .mock_all_auths()
+        .execute_admin_action(&signer_a, &pause));
+    assert_eq!(env.auths()[0].0, signer_a);
+    // A second approval from the same admin does not count.
+    assert!(!client
+        .mock_all_auths()
+        .execute_admin_action(&signer_a, &pause));
+    assert!(!client.is_paused());
+
+    assert!(client
+        .mock_all_auths()
+        .execute_admin_action(&signer_c, &pause));
+    assert!(client.is_paused());
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&381, &50),
+        Error::ContractPaused,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant(&382, &recipient, &1_000, &10),
+        Error::ContractPaused,
+    );
+
+    // Approvals were cleared when the pause ran, so unpausing needs two fresh ones.
+    let unpause = AdminAction::SetPaused(false);
+    assert!(!client
+        .mock_all_auths()
+        .execute_admin_action(&signer_b, &unpause));
+    assert!(client.is_paused());
+    assert!(client
+        .mock_all_auths()
+        .execute_admin_action(&signer_a, &unpause));
+    assert!(!client.is_paused());
+    set_timestamp(&env, 10);
+    client.mock_all_auths().withdraw(&381, &50);
+
+    // Once configured, the multisig can only be changed with its own approval.
+    let solo = AdminMultisig {
+        admins: vec![&env, signer_a.clone()],
+        threshold: 1,
+    };
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_set_admin_multisig(&solo.admins, &1),
+        Error::NotAuthorized,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_execute_admin_action(
+            &signer_a,
+            &AdminAction::SetMultisig(AdminMultisig {
+                admins: solo.admins.clone(),
+                threshold: 2,
+            }),
+        ),
+        Error::InvalidThreshold,
+    );
+    let replace = AdminAction::SetMultisig(solo.clone());
+    assert!(!client
+        .mock_all_auths()
+        .execute_admin_action(&signer_b, &replace));
+    assert!(client
+        .mock_all_auths()
+        .execute_admin_action(&signer_c, &replace));
+    assert_eq!(client.get_admin_multisig(), Some(solo));
+    assert!(client
+        .mock_all_auths()
+        .execute_admin_action(&signer_a, &pause));
+    assert!(client.is_paused());
+}
+
+#[test]
+fn test_pause_still_allows_cancel_and_refund() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let signer = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 2_000);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&390, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .create_grant(&391, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .set_admin_multisig(&vec![&env, signer.clone()], &1);
+    assert!(client
+        .mock_all_auths()
+        .execute_admin_action(&signer, &AdminAction::SetPaused(true)));
+    assert_eq!(client.get_pause_exemptions().len(), 4);
+
+    // Releases and fund-ins stay blocked...
+    set_timestamp(&env, 40);
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&390, &100),
+        Error::ContractPaused,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant(&392, &recipient, &1_000, &10),
+        Error::ContractPaused,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_decline_grant(&391),
+        Error::ContractPaused,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_slash_inactive_grant(&391),
+        Error::ContractPaused,
+    );
+
+    // ...while the admin can still cancel and pull the refund out.
+    client.mock_all_auths().cancel_grant(&390);
+    assert_eq!(client.mock_all_auths().claim_refund(&390), 600);
+    assert_eq!(token_client.balance(&treasury), 600);
+
+    // Emptying the exemption list blocks those too.
+    assert!(client
+        .mock_all_auths()
+        .execute_admin_action(&signer, &AdminAction::SetPauseExemptions(vec![&env])));
+    assert_contract_error(
+        client.mock_all_auths().try_cancel_grant(&391),
+        Error::ContractPaused,
+    );
+    assert_eq!(client.get_grant(&391).status, GrantStatus::Active);
+}
+
+#[test]
+fn test_withdraw_distinguishes_zero_from_over_withdrawal() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&393, &recipient, &1_000, &10);
+
+    set_timestamp(&env, 25);
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&393, &0),
+        Error::InvalidAmount,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&393, &251),
+        Error::InsufficientReleasedBalance,
+    );
+    let logs = env.logs().all();
+    assert!(logs
+        .last()
+        .unwrap()
+        .contains("withdraw exceeds released balance: requested, available"));
+    assert_eq!(
+        client.preview_withdraw(&393, &251).error,
+        Some(Error::InsufficientReleasedBalance as u32)
+    );
+
+    client.mock_all_auths().withdraw(&393, &250);
+    assert_eq!(client.get_grant(&393).withdrawn, 250);
+}
+
+#[test]
+fn test_lock_terms_blocks_changes_but_not_withdrawals() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&394, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&395, &recipient, &1_000, &100, &1_000, &0);
+    assert_contract_error(
+        client.mock_all_auths().try_lock_terms(&396),
+        Error::GrantNotFound,
+    );
+
+    client.mock_all_auths().lock_terms(&394);
+    client.mock_all_auths().lock_terms(&395);
+    assert!(client.is_terms_locked(&394));
+    assert!(client.is_terms_locked(&395));
+    assert!(!client.is_terms_locked(&396));
+
+    set_timestamp(&env, 10);
+    assert_contract_error(
+        client.mock_all_auths().try_update_rate(&394, &20),
+        Error::TermsLocked,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_reschedule_grant(&394, &500),
+        Error::TermsLocked,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_set_vesting_curve(&395, &VestingCurveData::Quadratic),
+        Error::TermsLocked,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_set_vesting_rounding(&395, &RoundingData::Ceil),
+        Error::TermsLocked,
+    );
+    assert_eq!(client.get_grant(&394).flow_rate, 10);
+
+    client.mock_all_auths().withdraw(&394, &100);
+    assert_eq!(client.get_grant(&394).withdrawn, 100);
+}
+
+#[test]
+fn test_withdraw_all_grants_collects_in_one_call() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let other = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&310, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .create_grant(&311, &recipient, &1_000, &3);
+    client
+        .mock_all_auths()
+        .create_grant(&312, &other, &1_000, &10);
+
+    set_timestamp(&env, 20);
+    let total = client
+        .mock_all_auths()
+        .withdraw_all_grants(&recipient, &vec![&env, 310_u64, 311, 312, 999]);
+    assert_eq!(total, 260);
+
+    let auths = env.auths();
+    assert_eq!(auths.len(), 1);
+    assert_eq!(auths[0].0, recipient);
+
+    assert_eq!(client.get_grant(&310).withdrawn, 200);
+    assert_eq!(client.get_grant(&311).withdrawn, 60);
+    assert_eq!(client.get_grant(&312).withdrawn, 0);
+
+    // Nothing new accrued at the same timestamp.
+    let total = client
+        .mock_all_auths()
+        .withdraw_all_grants(&recipient, &vec![&env, 310_u64, 311]);
+    assert_eq!(total, 0);
+}
+
+#[test]
+fn test_mul_div_floor_is_exact_with_wide_intermediate() {
+    assert_eq!(mul_div_floor(10, 3, 4), Some(7));
+    assert_eq!(
+        mul_div_floor(u128::MAX, u128::MAX, u128::MAX),
+        Some(u128::MAX)
+    );
+    assert_eq!(mul_div_floor(u128::MAX, 2, 4), Some(u128::MAX / 2));
+    assert_eq!(mul_div_floor(u128::MAX, 3, 3), Some(u128::MAX));
+    assert_eq!(mul_div_floor(1 << 127, 1 << 127, 1 << 127), Some(1 << 127));
+    assert_eq!(mul_div_floor(1 << 127, 1 << 127, 1 << 126), None);
+    assert_eq!(mul_div_floor(u128::MAX, 2, 1), None);
+    assert_eq!(mul_div_floor(5, 5, 0), None);
+    // (2^128 - 1)^2 / (2^128 - 2) = 2^128 + 1/(2^128 - 2), which does not fit.
+    assert_eq!(mul_div_floor(u128::MAX, u128::MAX, u128::MAX - 1), None);
+}
+
+#[test]
+fn test_compute_claimable_balance_exact_at_u128_max() {
+    let total = u128::MAX;
+    for duration in [2_u64, 3, 7, 1_000, 315_360_000, u64::MAX] {
+        let mut previous = 0;
+        let mut points = [
+            0_u64,
+            1,
+            2,
+            duration / 3,
+            duration / 2,
+            duration - 1,
+            duration,
+        ];
+        points.sort_unstable();
+        for elapsed in points {
+            let vested = compute_claimable_balance(total, 0, elapsed, duration);
+            assert!(vested >= previous);
+            assert!(vested <= total);
+            if elapsed < duration {
+                assert!(vested < total);
+            }
+            previous = vested;
+        }
+        assert_eq!(
+            compute_claimable_balance(total, 0, duration / 2, duration),
+            mul_div_floor(total, u128::from(duration / 2), u128::from(duration)).unwrap()
+        );
+    }
+    // floor((2^128 - 1) / 3) is exact because 2^128 - 1 is divisible by 3.
+    assert_eq!(compute_claimable_balance(total, 0, 1, 3), total / 3);
+    assert_eq!(compute_claimable_balance(total, 0, 2, 3), total / 3 * 2);
+}
+
+#[test]
+fn test_transfer_grantee_rights_moves_grant_to_new_grantee() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    let grant_id: u64 = 321;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+
+    set_timestamp(&env, 10);
+    client
+        .mock_all_auths()
+        .transfer_grantee_rights(&grant_id, &buyer);
+    assert_eq!(env.auths()[0].0, recipient);
+    assert_eq!(client.get_grant(&grant_id).recipient, buyer);
+    assert_eq!(client.get_grants_by_grantee(&recipient, &0, &10).len(), 0);
+    assert_eq!(
+        client.get_grants_by_grantee(&buyer, &0, &10),
+        vec![&env, grant_id]
+    );
+
+    let history = client.get_history(&grant_id, &1, &10);
+    let entry = history.get(0).unwrap();
+    assert_eq!(entry.action, HistoryAction::RecipientChanged);
+    assert_eq!(entry.actor, recipient);
+
+    // Accrued funds travel with the grant.
+    client.mock_all_auths().withdraw(&grant_id, &100);
+    assert_eq!(env.auths()[0].0, buyer);
+}
+
+#[test]
+fn test_transfer_grantee_rights_blocked_when_locked() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let buyer = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    let grant_id: u64 = 322;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .set_grantee_transfer_locked(&grant_id, &true);
+    assert_eq!(env.auths()[0].0, admin);
+
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_transfer_grantee_rights(&grant_id, &buyer),
+        Error::NotAuthorized,
+    );
+    assert_eq!(client.get_grant(&grant_id).recipient, recipient);
+
+    client
+        .mock_all_auths()
+        .set_grantee_transfer_locked(&grant_id, &false);
+    client
+        .mock_all_auths()
+        .transfer_grantee_rights(&grant_id, &buyer);
+    assert_eq!(client.get_grant(&grant_id).recipient, buyer);
+}
+
+#[test]
+fn test_completion_pays_bonus_exactly_once() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+    let bonus_token = create_token(&env, &admin, &admin, 500);
+    let bonus_client = token::Client::new(&env, &bonus_token);
+
+    let grant_id: u64 = 323;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    assert_contract_error(
+        client.mock_all_auths().try_create_grant_with_bonus(
+            &grant_id,
+            &recipient,
+            &1_000,
+            &10,
+            &0,
+            &bonus_token,
+        ),
+        Error::InvalidAmount,
+    );
+    client.mock_all_auths().create_grant_with_bonus(
+        &grant_id,
+        &recipient,
+        &1_000,
+        &10,
+        &200,
+        &bonus_token,
+    );
+    assert_eq!(bonus_client.balance(&admin), 300);
+    assert_eq!(bonus_client.balance(&contract_id), 200);
+
+    // The escrowed bonus is not rescuable.
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_rescue_tokens(&bonus_token, &1, &admin),
+        Error::RescueWouldViolateAllocated,
+    );
+
+    set_timestamp(&env, 50);
+    client.mock_all_auths().withdraw(&grant_id, &500);
+    assert_eq!(bonus_client.balance(&recipient), 0);
+
+    set_timestamp(&env, 100);
+    client.mock_all_auths().withdraw(&grant_id, &500);
+    assert_eq!(client.get_grant(&grant_id).status, GrantStatus::Completed);
+    assert_eq!(bonus_client.balance(&recipient), 200);
+    assert_eq!(bonus_client.balance(&contract_id), 0);
+    assert_eq!(client.get_grant_bonus(&grant_id), None);
+
+    // Nothing left to pay on later calls against the completed grant.
+    set_timestamp(&env, 200);
+    assert!(client.mock_all_auths().try_withdraw(&grant_id, &1).is_err());
+    assert_eq!(bonus_client.balance(&recipient), 200);
+}
+
+#[test]
+fn test_bonus_payout_rejects_misbehaving_token() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+    let bonus_token = create_mock_token(&env, 0, &admin, 200);
+
+    let grant_id: u64 = 402;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client.mock_all_auths().create_grant_with_bonus(
+        &grant_id,
+        &recipient,
+        &1_000,
+        &10,
+        &200,
+        &bonus_token.address,
+    );
+
+    // The bonus token debits the contract for only half of what it sends.
+    bonus_token.set_debit_bps(&5_000);
+    set_timestamp(&env, 100);
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&grant_id, &1_000),
+        Error::TransferFailed,
+    );
+    assert_eq!(client.get_grant(&grant_id).withdrawn, 0);
+    assert_eq!(bonus_token.balance(&recipient), 0);
+    assert!(client.get_grant_bonus(&grant_id).is_some());
+
+    bonus_token.set_debit_bps(&10_000);
+    client.mock_all_auths().withdraw(&grant_id, &1_000);
+    assert_eq!(bonus_token.balance(&recipient), 200);
+}
+
+#[test]
+fn test_cancel_refunds_bonus_to_admin() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+    let bonus_token = create_token(&env, &admin, &admin, 200);
+    let bonus_client = token::Client::new(&env, &bonus_token);
+
+    let grant_id: u64 = 324;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client.mock_all_auths().create_grant_with_bonus(
+        &grant_id,
+        &recipient,
+        &1_000,
+        &10,
+        &200,
+        &bonus_token,
+    );
+
+    set_timestamp(&env, 30);
+    client.mock_all_auths().cancel_grant(&grant_id);
+    assert_eq!(bonus_client.balance(&admin), 200);
+    assert_eq!(bonus_client.balance(&contract_id), 0);
+    assert_eq!(client.get_grant_bonus(&grant_id), None);
+
+    // Draining the accrued balance of a cancelled grant does not pay the bonus.
+    client.mock_all_auths().withdraw(&grant_id, &300);
+    assert_eq!(bonus_client.balance(&recipient), 0);
+}
+
+#[test]
+fn test_time_to_vest_round_trips_with_compute_claimable_balance() {
+    let start = 1_000_u64;
+    assert_eq!(time_to_vest(1_000, start, 100, 0), Some(start));
+    assert_eq!(time_to_vest(1_000, start, 100, 1_001), None);
+    assert_eq!(time_to_vest(1_000, start, 0, 1_000), Some(start));
+    assert_eq!(time_to_vest(1_000, start, 100, 500), Some(start + 50));
+    assert_eq!(time_to_vest(1_000, u64::MAX - 10, 100, 1), None);
+
+    for total in [1_u128, 3, 7, 1_000, 500_000, 999_999_937, u128::MAX] {
+        for duration in [1_u64, 3, 13, 1_000, 86_400, 315_360_000, u64::MAX - start] {
+            for target in [0, 1, 2, total / 7, total / 3, total / 2, total - 1, total] {
+                if target > total {
+                    continue;
+                }
+                let at = time_to_vest(total, start, duration, target).unwrap();
+                assert!(compute_claimable_balance(total, start, at, duration) >= target);
+                if at > start {
+                    assert!(compute_claimable_balance(total, start, at - 1, duration) < target);
+                } else {
+                    assert_eq!(at, start);
+                }
+            }
+            // The full amount is only vested once the whole duration has elapsed.
+            assert_eq!(
+                time_to_vest(total, start, duration, total),
+                Some(start + duration)
+            );
+            if total < u128::MAX {
+                assert_eq!(time_to_vest(total, start, duration, total + 1), None);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_compute_claimable_excluding_pauses_freezes_elapsed_time() {
+    let (total, start, duration) = (1_000_u128, 1_000_u64, 1_000_u64);
+    assert_eq!(
+        compute_claimable_excluding_pauses(total, start, 1_500, duration, 0),
+        compute_claimable_balance(total, start, 1_500, duration)
+    );
+    assert_eq!(
+        compute_claimable_excluding_pauses(total, start, 1_500, duration, 200),
+        300
+    );
+    // A pause longer than the time elapsed leaves nothing vested rather than underflowing.
+    assert_eq!(
+        compute_claimable_excluding_pauses(total, start, 1_500, duration, 501),
+        0
+    );
+    assert_eq!(
+        compute_claimable_excluding_pauses(total, start, 1_500, duration, u64::MAX),
+        0
+    );
+    assert_eq!(
+        compute_claimable_excluding_pauses(total, start, 500, duration, 100),
+        0
+    );
+    // The end moves back by the paused time.
+    assert_eq!(
+        compute_claimable_excluding_pauses(total, start, 2_199, duration, 200),
+        999
+    );
+    assert_eq!(
+        compute_claimable_excluding_pauses(total, start, 2_200, duration, 200),
+        total
+    );
+}
+
+#[test]
+fn test_long_pause_freezes_vesting_grant() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 365_000);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let day: u64 = 86_400;
+    let grant_id: u64 = 325;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client.mock_all_auths().create_grant_vested(
+        &grant_id,
+        &recipient,
+        &365_000,
+        &0,
+        &(365 * day),
+        &0,
+    );
+
+    set_timestamp(&env, 100 * day);
+    client.mock_all_auths().pause_vesting(&grant_id);
+    assert_eq!(env.auths()[0].0, admin);
+    assert_contract_error(
+        client.mock_all_auths().try_pause_vesting(&grant_id),
+        Error::InvalidState,
+    );
+
+    // Three months frozen: the vested amount does not move.
+    set_timestamp(&env, 190 * day);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 100_000);
+    assert_contract_error(
+        client.mock_all_auths().try_claim_vested(&grant_id),
+        Error::NothingToClaim,
+    );
+
+    client.mock_all_auths().resume_vesting(&grant_id);
+    assert_contract_error(
+        client.mock_all_auths().try_resume_vesting(&grant_id),
+        Error::InvalidState,
+    );
+    let schedule = client.get_vesting_schedule(&grant_id);
+    assert_eq!(schedule.total_paused_seconds, 90 * day);
+    assert_eq!(schedule.paused_at, None);
+
+    set_timestamp(&env, 200 * day);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 10_000);
+
+    // The schedule now ends 90 days late.
+    set_timestamp(&env, 455 * day - 1);
+    assert!(client.mock_all_auths().claim_vested(&grant_id) < 255_000);
+    set_timestamp(&env, 455 * day);
+    client.mock_all_auths().claim_vested(&grant_id);
+    assert_eq!(token_client.balance(&recipient), 365_000);
+    assert_eq!(
+        client.get_vesting_schedule(&grant_id).status,
+        GrantStatus::Completed
+    );
+
+    let history = client.get_history(&grant_id, &1, &2);
+    assert_eq!(history.get(0).unwrap().action, HistoryAction::Paused);
+}
+
+#[test]
+fn test_claim_vested_streams_small_frequent_claims() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 10_000);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let grant_id: u64 = 326;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &9_000, &0, &3_000, &0);
+    assert_eq!(client.get_stream_rate(&grant_id), 3);
+
+    // Claiming twice at the same timestamp is a clean NothingToClaim.
+    set_timestamp(&env, 10);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 30);
+    assert_contract_error(
+        client.mock_all_auths().try_claim_vested(&grant_id),
+        Error::NothingToClaim,
+    );
+
+    set_timestamp(&env, 11);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 3);
+    assert_eq!(client.get_vesting_schedule(&grant_id).claimed, 33);
+
+    set_timestamp(&env, 3_000);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 8_967);
+    assert_eq!(token_client.balance(&recipient), 9_000);
+    assert_eq!(client.get_stream_rate(&grant_id), 0);
+
+    // A second's accrual that rounds down to zero is also NothingToClaim.
+    let slow_id: u64 = 327;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&slow_id, &recipient, &1_000, &0, &3_000, &0);
+    assert_eq!(client.get_stream_rate(&slow_id), 0);
+    set_timestamp(&env, 1);
+    assert_contract_error(
+        client.mock_all_auths().try_claim_vested(&slow_id),
+        Error::NothingToClaim,
+    );
+    set_timestamp(&env, 3);
+    assert_eq!(client.mock_all_auths().claim_vested(&slow_id), 1);
+
+    let streaming_id: u64 = 328;
+    client
+        .mock_all_auths()
+        .create_grant(&streaming_id, &recipient, &1_000, &7);
+    assert_eq!(client.get_stream_rate(&streaming_id), 7);
+}
+
+#[test]
+fn test_revoke_vesting_keeps_vested_and_refunds_admin() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 2_000);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let grant_id: u64 = 330;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &1_000, &0, &1_000, &0);
+
+    // 40% through the schedule.
+    set_timestamp(&env, 400);
+    assert_eq!(client.mock_all_auths().revoke_vesting(&grant_id), 600);
+    assert_eq!(env.auths()[0].0, admin);
+    assert_eq!(token_client.balance(&admin), 600);
+    assert_eq!(token_client.balance(&treasury), 0);
+    assert_contract_error(
+        client.mock_all_auths().try_revoke_vesting(&grant_id),
+        Error::InvalidState,
+    );
+
+    // Later claims honor only the entitlement fixed at revocation.
+    set_timestamp(&env, 1_000);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 400);
+    assert_contract_error(
+        client.mock_all_auths().try_claim_vested(&grant_id),
+        Error::NothingToClaim,
+    );
+    let schedule = client.get_vesting_schedule(&grant_id);
+    assert_eq!(schedule.status, GrantStatus::Cancelled);
+    assert_eq!(schedule.stopped_at, Some(400));
+    let history = client.get_history(&grant_id, &1, &1);
+    assert_eq!(history.get(0).unwrap().action, HistoryAction::Revoked);
+
+    // Revoking a fully vested schedule refunds nothing.
+    let vested_id: u64 = 331;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&vested_id, &recipient, &1_000, &0, &100, &0);
+    set_timestamp(&env, 100);
+    assert_eq!(client.mock_all_auths().revoke_vesting(&vested_id), 0);
+    assert_eq!(token_client.balance(&admin), 600);
+    assert_eq!(client.mock_all_auths().claim_vested(&vested_id), 1_000);
+    // Claiming everything does not turn the revoked schedule into a completed one.
+    assert_eq!(
+        client.get_vesting_schedule(&vested_id).status,
+        GrantStatus::Cancelled
+    );
+    assert_eq!(client.get_completed_count(&recipient), 0);
+}
+
+#[test]
+fn test_quadratic_curve_is_back_loaded() {
+    let (total, start, duration) = (1_000_u128, 100_u64, 1_000_u64);
+    let linear = |now| {
+        compute_claimable_curve(
+            total,
+            start,
+            now,
+            duration,
+            VestingCurve::Linear,
+            Rounding::Floor,
+        )
+    };
+    let quadratic = |now| {
+        compute_claimable_curve(
+            total,
+            start,
+            now,
+            duration,
+            VestingCurve::Quadratic,
+            Rounding::Floor,
+        )
+    };
+
+    assert_eq!(linear(600), 500);
+    assert_eq!(quadratic(600), 250);
+    assert_eq!(quadratic(100), 0);
+    assert_eq!(quadratic(1_099), 998);
+    assert_eq!(quadratic(1_100), total);
+    assert_eq!(quadratic(u64::MAX), total);
+
+    for total in [1_u128, 7, 999_999_937, u128::MAX] {
+        for duration in [1_u64, 3, 1_000, 315_360_000, u64::MAX - start] {
+            let mut previous = 0;
+            let mut points = [start, start + 1, start + duration / 2, start + duration - 1];
+            points.sort_unstable();
+            for now in points {
+                let vested = compute_claimable_curve(
+                    total,
+                    start,
+                    now,
+                    duration,
+                    VestingCurve::Quadratic,
+                    Rounding::Floor,
+                );
+                assert!(vested >= previous);
+                assert!(vested <= compute_claimable_balance(total, start, now, duration));
+                previous = vested;
+            }
+            assert_eq!(
+                compute_claimable_curve(
+                    total,
+                    start,
+                    start,
+                    duration,
+                    VestingCurve::Quadratic,
+                    Rounding::Floor
+                ),
+                0
+            );
+            assert_eq!(
+                compute_claimable_curve(
+                    total,
+                    start,
+                    start + duration,
+                    duration,
+                    VestingCurve::Quadratic,
+                    Rounding::Floor
+                ),
+                total
+            );
+        }
+    }
+}
+
+#[test]
+fn test_vesting_grant_follows_quadratic_curve() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+
+    let grant_id: u64 = 332;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &1_000, &100, &1_000, &0);
+    assert_eq!(
+        client.get_vesting_schedule(&grant_id).curve,
+        VestingCurveData::Linear
+    );
+    client
+        .mock_all_auths()
+        .set_vesting_curve(&grant_id, &VestingCurveData::Quadratic);
+    assert_eq!(env.auths()[0].0, admin);
+
+    set_timestamp(&env, 600);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 250);
+    // Too late to switch once the schedule has started.
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_set_vesting_curve(&grant_id, &VestingCurveData::Linear),
+        Error::InvalidState,
+    );
+
+    set_timestamp(&env, 1_100);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 750);
+}
+
+#[test]
+fn test_cancel_defers_refund_until_claimed() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let grant_id: u64 = 333;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+    assert_contract_error(
+        client.mock_all_auths().try_claim_refund(&grant_id),
+        Error::NothingToClaim,
+    );
+
+    set_timestamp(&env, 30);
+    client.mock_all_auths().cancel_grant(&grant_id);
+    assert_eq!(token_client.balance(&treasury), 0);
+    assert_eq!(client.get_refund_owed(&grant_id), 700);
+
+    // The owed refund is still allocated, so it cannot be rescued in the meantime.
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_rescue_tokens(&grant_token, &1, &admin),
+        Error::RescueWouldViolateAllocated,
+    );
+
+    assert_eq!(client.mock_all_auths().claim_refund(&grant_id), 700);
+    assert_eq!(env.auths()[0].0, admin);
+    assert_eq!(token_client.balance(&treasury), 700);
+    assert_eq!(client.get_refund_owed(&grant_id), 0);
+    assert_contract_error(
+        client.mock_all_auths().try_claim_refund(&grant_id),
+        Error::NothingToClaim,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_claim_refund(&999),
+        Error::GrantNotFound,
+    );
+}
+
+#[test]
+fn test_preview_schedule_matches_compute_claimable_balance() {
+    for total in [1_u128, 7, 999_999_937, u128::MAX] {
+        for duration in [0_u64, 1, 13, 1_000, 315_360_000, u64::MAX - 1_000] {
+            for points in [0_u32, 1, 2, 3, 10, 100, 1_000] {
+                let mut count = 0;
+                let mut previous = (0, 0);
+                for (i, (now, vested)) in
+                    preview_schedule(total, 1_000, duration, points).enumerate()
+                {
+                    assert_eq!(
+                        vested,
+                        compute_claimable_balance(total, 1_000, now, duration)
+                    );
+                    if i == 0 {
+                        assert_eq!(now, 1_000);
+                        if duration > 0 {
+                            assert_eq!(vested, 0);
+                        }
+                    } else {
+                        assert!(now >= previous.0);
+                        assert!(vested >= previous.1);
+                    }
+                    previous = (now, vested);
+                    count += 1;
+                }
+                assert_eq!(count, points.clamp(2, MAX_PREVIEW_POINTS));
+                assert_eq!(previous, (1_000 + duration, total));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_preview_vesting_schedule_samples_on_chain_schedule() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    let grant_id: u64 = 334;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &1_000, &100, &1_000, &300);
+
+    let table = client.preview_vesting_schedule(&grant_id, &5);
+    assert_eq!(
+        table,
+        vec![
+            &env,
+            (100_u64, 0_i128),
+            (350, 0),
+            (600, 500),
+            (850, 750),
+            (1_100, 1_000)
+        ]
+    );
+    assert_eq!(
+        client.preview_vesting_schedule(&grant_id, &10_000).len(),
+        MAX_PREVIEW_POINTS
+    );
+    assert_contract_error(
+        client.try_preview_vesting_schedule(&1_000, &5),
+        Error::GrantNotFound,
+    );
+}
+
+#[test]
+fn test_grant_age_and_idle_time() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    let grant_id: u64 = 335;
+    set_timestamp(&env, 1_000);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &1);
+    assert_eq!(client.get_grant_age(&grant_id), 0);
+    assert_eq!(client.get_idle_time(&grant_id), 0);
+
+    set_timestamp(&env, 1_300);
+    assert_eq!(client.get_grant_age(&grant_id), 300);
+    assert_eq!(client.get_idle_time(&grant_id), 300);
+
+    client.mock_all_auths().withdraw(&grant_id, &100);
+    set_timestamp(&env, 1_500);
+    assert_eq!(client.get_grant_age(&grant_id), 500);
+    assert_eq!(client.get_idle_time(&grant_id), 200);
+
+    // Vesting grants are covered too.
+    client
+        .mock_all_auths()
+        .create_grant_vested(&336, &recipient, &1_000, &0, &1_000, &0);
+    set_timestamp(&env, 1_600);
+    assert_eq!(client.get_grant_age(&336), 100);
+    assert_eq!(client.get_idle_time(&336), 100);
+
+    // Admin changes to a grant's settings count as activity.
+    client
+        .mock_all_auths()
+        .set_admin_withdraws(&grant_id, &true);
+    client.mock_all_auths().lock_terms(&336);
+    assert_eq!(client.get_idle_time(&grant_id), 0);
+    assert_eq!(client.get_idle_time(&336), 0);
+    let history = client.get_history(&grant_id, &0, &10);
+    assert_eq!(
+        history.last().unwrap().action,
+        HistoryAction::AdminWithdrawsUpdated
+    );
+    set_timestamp(&env, 1_650);
+    assert_eq!(client.get_idle_time(&grant_id), 50);
+
+    assert_contract_error(client.try_get_grant_age(&999), Error::GrantNotFound);
+    assert_contract_error(client.try_get_idle_time(&999), Error::GrantNotFound);
+}
+
+#[test]
+fn test_vesting_by_ledger_sequence_ignores_timestamps() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let grant_id: u64 = 337;
+    set_timestamp(&env, 0);
+    set_sequence(&env, 100);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested_by_ledger(&grant_id, &recipient, &1_000, &200, &1_000, &100);
+    assert_eq!(
+        client.get_vesting_schedule(&grant_id).time_basis,
+        TimeBasis::LedgerSequence
+    );
+
+    // Timestamps far past the schedule do not matter; only the sequence does.
+    set_timestamp(&env, 1_000_000);
+    set_sequence(&env, 299);
+    assert_contract_error(
+        client.mock_all_auths().try_claim_vested(&grant_id),
+        Error::NothingToClaim,
+    );
+
+    set_sequence(&env, 300);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 100);
+
+    set_sequence(&env, 700);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 400);
+
+    set_sequence(&env, 1_200);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 500);
+    assert_eq!(token_client.balance(&recipient), 1_000);
+    assert_eq!(
+        client.get_vesting_schedule(&grant_id).status,
+        GrantStatus::Completed
+    );
+}
+
+#[test]
+fn test_vesting_start_may_be_retroactive_or_delayed() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 3_000);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let day: u64 = 86_400;
+    let now = 1_000 * day;
+    set_timestamp(&env, now);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    assert_eq!(client.get_max_start_offset(), Some(3_650 * day));
+
+    // Retroactive: 40 of 100 days already vested at creation.
+    client.mock_all_auths().create_grant_vested(
+        &338,
+        &recipient,
+        &1_000,
+        &(now - 40 * day),
+        &(100 * day),
+        &0,
+    );
+    assert_eq!(client.get_vesting_schedule(&338).start, now - 40 * day);
+    assert_eq!(client.mock_all_auths().claim_vested(&338), 400);
+
+    // So far back that it is fully vested at creation.
+    client.mock_all_auths().create_grant_vested(
+        &339,
+        &recipient,
+        &1_000,
+        &(now - 500 * day),
+        &(100 * day),
+        &0,
+    );
+    assert_eq!(client.mock_all_auths().claim_vested(&339), 1_000);
+
+    // Delayed: nothing until the start, then linear.
+    client.mock_all_auths().create_grant_vested(
+        &340,
+        &recipient,
+        &1_000,
+        &(now + 10 * day),
+        &(100 * day),
+        &0,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_claim_vested(&340),
+        Error::NothingToClaim,
+    );
+    set_timestamp(&env, now + 60 * day);
+    assert_eq!(client.mock_all_auths().claim_vested(&340), 500);
+    assert_eq!(token_client.balance(&recipient), 400 + 1_000 + 500);
+
+    // Starts outside the configured window are rejected in both directions.
+    set_timestamp(&env, now);
+    client
+        .mock_all_auths()
+        .set_max_start_offset(&Some(30 * day));
+    assert_eq!(env.auths()[0].0, admin);
+    assert_contract_error(
+        client.mock_all_auths().try_create_grant_vested(
+            &341,
+            &recipient,
+            &1_000,
+            &(now - 31 * day),
+            &day,
+            &0,
+        ),
+        Error::StartOutOfRange,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_create_grant_vested(
+            &341,
+            &recipient,
+            &1_000,
+            &(now + 31 * day),
+            &day,
+            &0,
+        ),
+        Error::StartOutOfRange,
+    );
+    client.mock_all_auths().create_grant_vested(
+        &341,
+        &recipient,
+        &1_000,
+        &(now + 30 * day),
+        &day,
+        &0,
+    );
+}
+
+#[test]
+fn test_daily_claims_distribute_exactly_total_under_both_roundings() {
+    let day: u64 = 86_400;
+    for rounding in [Rounding::Floor, Rounding::Ceil] {
+        for total in [1_u128, 7, 10, 365, 1_000, 999_999_937] {
+            for days in [1_u64, 3, 7, 30, 365] {
+                let duration = days * day + 1;
+                let mut claimed = 0;
+                let mut now = 0;
+                while now <= duration + day {
+                    let vested =
+                        compute_claimable_balance_rounded(total, 0, now, duration, rounding);
+                    assert!(vested >= claimed);
+                    assert!(vested <= total);
+                    claimed = vested;
+                    now += day;
+                }
+                assert_eq!(claimed, total);
+            }
+        }
+    }
+
+    // Zero-decimal token: ceil is one unit ahead of floor mid-schedule, never beyond total.
+    let floor = compute_claimable_balance_rounded(10, 0, day, 3 * day, Rounding::Floor);
+    let ceil = compute_claimable_balance_rounded(10, 0, day, 3 * day, Rounding::Ceil);
+    assert_eq!((floor, ceil), (3, 4));
+    assert_eq!(
+        compute_claimable_balance_rounded(10, 0, 3 * day - 1, 3 * day, Rounding::Ceil),
+        10
+    );
+    assert_eq!(
+        compute_claimable_balance_rounded(9, 0, day, 3 * day, Rounding::Ceil),
+        3
+    );
+    assert_eq!(
+        compute_claimable_balance_rounded(u128::MAX, 0, 1, 2, Rounding::Ceil),
+        u128::MAX / 2 + 1
+    );
+    assert_eq!(Rounding::default(), Rounding::Floor);
+}
+
+#[test]
+fn test_vesting_grant_rounds_up_when_configured() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 10);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let grant_id: u64 = 342;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &10, &0, &3, &0);
+    client
+        .mock_all_auths()
+        .set_vesting_rounding(&grant_id, &RoundingData::Ceil);
+    assert_eq!(env.auths()[0].0, admin);
+
+    let mut claims = [0_i128; 3];
+    for (i, claim) in claims.iter_mut().enumerate() {
+        set_timestamp(&env, i as u64 + 1);
+        *claim = client.mock_all_auths().claim_vested(&grant_id);
+    }
+    assert_eq!(claims, [4, 3, 3]);
+    assert_eq!(token_client.balance(&recipient), 10);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_set_vesting_rounding(&grant_id, &RoundingData::Floor),
+        Error::InvalidState,
+    );
+}
+
+/// Signs `(contract, grant_id, amount, nonce)` the way withdraw_signed verifies it.
+fn sign_withdrawal(
+    env: &Env,
+    key: &SigningKey,
+    contract: &Address,
+    grant_id: u64,
+    amount: i128,
+    nonce: u64,
+) -> BytesN<64> {
+    let message = (contract.clone(), grant_id, amount, nonce).to_xdr(env);
+    let mut buf = [0_u8; 256];
+    let len = message.len() as usize;
+    message.copy_into_slice(&mut buf[..len]);
+    BytesN::from_array(env, &key.sign(&buf[..len]).to_bytes())
+}
+
+#[test]
+fn test_withdraw_signed_accepts_signature_once() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let public_key = BytesN::from_array(&env, &key.verifying_key().to_bytes());
+
+    let grant_id: u64 = 343;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+
+    set_timestamp(&env, 50);
+    let signature = sign_withdrawal(&env, &key, &contract_id, grant_id, 200, 1);
+    // No signer registered yet.
+    assert_contract_error(
+        client.try_withdraw_signed(&grant_id, &200, &signature, &1),
+        Error::NotAuthorized,
+    );
+
+    client
+        .mock_all_auths()
+        .set_withdraw_signer(&recipient, &Some(public_key));
+    assert_eq!(env.auths()[0].0, recipient);
+
+    // The relayer needs no authorization of its own.
+    client.withdraw_signed(&grant_id, &200, &signature, &1);
+    assert!(env.auths().is_empty());
+    assert_eq!(client.get_grant(&grant_id).withdrawn, 200);
+    assert_eq!(client.get_withdraw_nonce(&recipient), Some(1));
+
+    // Replaying the same signature, or any nonce not above the last, is rejected.
+    assert_contract_error(
+        client.try_withdraw_signed(&grant_id, &200, &signature, &1),
+        Error::InvalidNonce,
+    );
+    let stale = sign_withdrawal(&env, &key, &contract_id, grant_id, 100, 0);
+    assert_contract_error(
+        client.try_withdraw_signed(&grant_id, &100, &stale, &0),
+        Error::InvalidNonce,
+    );
+
+    let next = sign_withdrawal(&env, &key, &contract_id, grant_id, 100, 5);
+    client.withdraw_signed(&grant_id, &100, &next, &5);
+    assert_eq!(client.get_grant(&grant_id).withdrawn, 300);
+    assert_eq!(client.get_withdraw_nonce(&recipient), Some(5));
+}
+
+#[test]
+fn test_withdraw_signed_rejects_signature_for_other_amount() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let public_key = BytesN::from_array(&env, &key.verifying_key().to_bytes());
+
+    let grant_id: u64 = 344;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .set_withdraw_signer(&recipient, &Some(public_key));
+
+    set_timestamp(&env, 50);
+    // Checks that need no signature fail with contract errors before it is verified.
+    let garbage = BytesN::from_array(&env, &[0; 64]);
+    assert_contract_error(
+        client.try_withdraw_signed(&grant_id, &600, &garbage, &1),
+        Error::InsufficientReleasedBalance,
+    );
+    assert_contract_error(
+        client.try_withdraw_signed(&grant_id, &0, &garbage, &1),
+        Error::InvalidAmount,
+    );
+
+    // Signed for 100 but submitted for 200: ed25519 verification traps in the host.
+    let signature = sign_withdrawal(&env, &key, &contract_id, grant_id, 100, 1);
+    assert_aborts(
+        "test::test_withdraw_signed_rejects_signature_for_other_amount",
+        || {
+            let _ = client.try_withdraw_signed(&grant_id, &200, &signature, &1);
+        },
+    );
+    // The signature is only good for the amount it covers.
+    client.withdraw_signed(&grant_id, &100, &signature, &1);
+    assert_eq!(client.get_grant(&grant_id).withdrawn, 100);
+}
+
+fn tranche(amount: i128, start: u64, duration: u64, cliff: u64) -> VestingTranche {
+    VestingTranche {
+        amount,
+        start,
+        duration,
+        cliff,
+    }
+}
+
+#[test]
+fn test_claim_vested_sums_overlapping_tranches() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let grant_id: u64 = 345;
+    set_timestamp(&env, 1_000);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    // 500 over 1_000s from now, plus 500 over 2_000s starting 500s later.
+    let tranches = vec![
+        &env,
+        tranche(500, 1_000, 1_000, 0),
+        tranche(500, 1_500, 2_000, 100),
+    ];
+    client
+        .mock_all_auths()
+        .create_grant_vested_tranches(&grant_id, &recipient, &1_000, &tranches);
+    assert_eq!(client.get_vesting_tranches(&grant_id), tranches);
+
+    let schedule = client.get_vesting_schedule(&grant_id);
+    assert_eq!(schedule.start, 1_000);
+    assert_eq!(schedule.duration, 2_500);
+    assert_eq!(schedule.cliff, 0);
+
+    // Only the first tranche has started; the second is inside its cliff.
+    set_timestamp(&env, 1_550);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 275);
+
+    // The first tranche is complete and the second is a quarter through.
+    set_timestamp(&env, 2_000);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 225 + 125);
+    set_timestamp(&env, 2_500);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 125);
+    assert_eq!(token_client.balance(&recipient), 750);
+
+    set_timestamp(&env, 3_500);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 250);
+    assert_eq!(token_client.balance(&recipient), 1_000);
+    assert_eq!(
+        client.get_vesting_schedule(&grant_id).status,
+        GrantStatus::Completed
+    );
+}
+
+#[test]
+fn test_create_grant_vested_tranches_validates_tranches() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    let grant_id: u64 = 346;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+
+    let uneven = vec![&env, tranche(500, 0, 100, 0), tranche(400, 50, 100, 0)];
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant_vested_tranches(&grant_id, &recipient, &1_000, &uneven),
+        Error::InvalidAmount,
+    );
+    let empty_tranche = vec![&env, tranche(1_000, 0, 100, 0), tranche(0, 50, 100, 0)];
+    assert_contract_error(
+        client.mock_all_auths().try_create_grant_vested_tranches(
+            &grant_id,
+            &recipient,
+            &1_000,
+            &empty_tranche,
+        ),
+        Error::InvalidAmount,
+    );
+    assert_contract_error(
+        client.mock_all_auths().try_create_grant_vested_tranches(
+            &grant_id,
+            &recipient,
+            &1_000,
+            &vec![&env],
+        ),
+        Error::InvalidAmount,
+    );
+
+    let mut too_many = vec![&env];
+    for i in 0..11 {
+        too_many.push_back(tranche(100, i * 10, 100, 0));
+    }
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant_vested_tranches(&grant_id, &recipient, &1_100, &too_many),
+        Error::TooManyTranches,
+    );
+    assert!(client.try_get_vesting_schedule(&grant_id).is_err());
+
+    // A single-schedule grant reports its whole amount as one tranche.
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &1_000, &10, &100, &20);
+    assert_eq!(
+        client.get_vesting_tranches(&grant_id),
+        vec![&env, tranche(1_000, 10, 100, 20)]
+    );
+}
+
+#[test]
+fn test_claim_vested_on_underfunded_contract_fails_cleanly() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 100);
+    let token_client = token::Client::new(&env, &grant_token);
+
+    let grant_id: u64 = 347;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &1_000, &0, &1_000, &0);
+
+    set_timestamp(&env, 500);
+    assert_contract_error(
+        client.mock_all_auths().try_claim_vested(&grant_id),
+        Error::InsufficientFunding,
+    );
+    assert_eq!(client.get_vesting_schedule(&grant_id).claimed, 0);
+    assert_eq!(token_client.balance(&recipient), 0);
+
+    token::StellarAssetClient::new(&env, &grant_token)
+        .mock_all_auths()
+        .mint(&contract_id, &400);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 500);
+    assert_eq!(token_client.balance(&recipient), 500);
+}
+
+#[test]
+fn test_claim_state_enforces_claim_invariants() {
+    let mut claims = ClaimState::default();
+    assert_eq!(claims.claimable_now(1_000, 100, 350, 1_000), 250);
+    assert_eq!(claims.record_claim(0, 250), Err(ClaimError::ZeroAmount));
+    assert_eq!(
+        claims.record_claim(251, 250),
+        Err(ClaimError::ExceedsAccrued)
+    );
+    assert_eq!(claims.record_claim(200, 250), Ok(()));
+    assert_eq!(claims.claimed(), 200);
+    assert_eq!(claims.claimable_now(1_000, 100, 350, 1_000), 50);
+    // Accrual below the claimed total, e.g. a stale reading, leaves nothing claimable.
+    assert_eq!(claims.claimable_of(150), 0);
+    assert_eq!(claims.record_claim(1, 150), Err(ClaimError::ExceedsAccrued));
+    assert_eq!(claims.claimed(), 200);
+
+    let mut resumed = ClaimState::new(200);
+    assert_eq!(resumed, claims);
+    assert_eq!(resumed.record_claim(800, 1_000), Ok(()));
+    assert_eq!(resumed.claimable_of(1_000), 0);
+}
+
+/// Deterministic xorshift64 so random claim sequences are reproducible.
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+#[test]
+fn test_claim_state_random_claim_sequences() {
+    let mut seed: u64 = 0x9E37_79B9_7F4A_7C15;
+    for _ in 0..500 {
+        let total = u128::from(next_random(&mut seed) % 1_000_000_000) + 1;
+        let start = next_random(&mut seed) % 1_000_000;
+        let duration = next_random(&mut seed) % 100_000;
+        let mut claims = ClaimState::default();
+        let mut now = start.saturating_sub(next_random(&mut seed) % 1_000);
+
+        for _ in 0..20 {
+            now += next_random(&mut seed) % (duration / 5 + 1);
+            let accrued = compute_claimable_balance(total, start, now, duration);
+            let claimable = claims.claimable_now(total, start, now, duration);
+            assert_eq!(claims.claimed() + claimable, accrued);
+
+            let before = claims.claimed();
+            let over = claimable + 1 + u128::from(next_random(&mut seed) % 1_000);
+            assert_eq!(
+                claims.record_claim(over, accrued),
+                Err(ClaimError::ExceedsAccrued)
+            );
+            assert_eq!(claims.claimed(), before);
+
+            if claimable > 0 {
+                let amount = u128::from(next_random(&mut seed)) % claimable + 1;
+                assert_eq!(claims.record_claim(amount, accrued), Ok(()));
+            }
+            assert!(claims.claimed() >= before);
+            assert!(claims.claimed() <= accrued);
+            assert!(claims.claimed() <= total);
+        }
+
+        // Claiming everything accrued once the schedule ends pays out exactly the total.
+        let end = start + duration;
+        let remaining = claims.claimable_now(total, start, end, duration);
+        if remaining > 0 {
+            assert_eq!(claims.record_claim(remaining, total), Ok(()));
+        }
+        assert_eq!(claims.claimed(), total);
+    }
+}
+
+#[test]
+fn test_is_funded_compares_balance_with_outstanding_amount() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 400);
+    let token_admin = token::StellarAssetClient::new(&env, &grant_token);
+
+    let grant_id: u64 = 348;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    assert_contract_error(client.try_is_funded(&grant_id), Error::GrantNotFound);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+    assert!(!client.is_funded(&grant_id));
+
+    token_admin.mock_all_auths().mint(&contract_id, &600);
+    assert!(client.is_funded(&grant_id));
+
+    // Withdrawals shrink what is owed.
+    set_timestamp(&env, 50);
+    client.mock_all_auths().withdraw(&grant_id, &500);
+    assert!(client.is_funded(&grant_id));
+
+    let vesting_id: u64 = 349;
+    client
+        .mock_all_auths()
+        .create_grant_vested(&vesting_id, &recipient, &2_000, &0, &1_000, &0);
+    assert!(!client.is_funded(&vesting_id));
+    token_admin.mock_all_auths().mint(&contract_id, &1_500);
+    assert!(client.is_funded(&vesting_id));
+}
+
+#[test]
+fn test_bps_helpers_at_extremes() {
+    let amounts = [
+        0,
+        1,
+        9_999,
+        10_000,
+        10_001,
+        u128::MAX / 2,
+        u128::MAX - 1,
+        u128::MAX,
+    ];
+    for amount in amounts {
+        for bps in [0, 1, 9_999, 10_000] {
+            let share = bps_of(amount, bps);
+            assert_eq!(
+                Some(share),
+                mul_div_floor(amount, u128::from(bps), 10_000),
+                "bps_of({amount}, {bps})"
+            );
+            let (split_share, rest) = split_bps(amount, bps);
+            assert_eq!(split_share, share);
+            assert_eq!(split_share + rest, amount);
+        }
+        assert_eq!(bps_of(amount, 0), 0);
+        assert_eq!(bps_of(amount, 10_000), amount);
+        // Anything above 100% is capped rather than overflowing.
+        assert_eq!(bps_of(amount, u32::MAX), amount);
+        assert_eq!(split_bps(amount, 20_000), (amount, 0));
+    }
+
+    assert_eq!(bps_of(u128::MAX, 1), u128::MAX / 10_000);
+    assert_eq!(bps_of(u128::MAX, 9_999), u128::MAX - u128::MAX / 10_000 - 1);
+    assert_eq!(split_bps(10_001, 9_999), (9_999, 2));
+    assert_eq!(split_bps(9_999, 1), (0, 9_999));
+
+    assert_eq!(validate_bps(0), Ok(0));
+    assert_eq!(validate_bps(BPS_DENOMINATOR), Ok(10_000));
+    assert_eq!(validate_bps(10_001), Err(BpsError::OutOfRange));
+    assert_eq!(validate_bps_total(&[]), Ok(0));
+    assert_eq!(validate_bps_total(&[2_500, 7_500]), Ok(10_000));
+    assert_eq!(validate_bps_total(&[9_999, 2]), Err(BpsError::OutOfRange));
+    assert_eq!(
+        validate_bps_total(&[u32::MAX, 1]),
+        Err(BpsError::OutOfRange)
+    );
+}
+
+#[test]
+fn test_scaled_amounts_split_at_grant_scale() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    let grant_id: u64 = 350;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &123_456_789_012, &12_345_678);
+
+    // Unscaled by default.
+    assert_eq!(client.get_amount_scale(&grant_id), 0);
+    assert_eq!(client.get_total_scaled(&grant_id), (123_456_789_012, 0));
+
+    client.mock_all_auths().set_amount_scale(&grant_id, &7);
+    assert_eq!(env.auths()[0].0, admin);
+    assert_eq!(client.get_amount_scale(&grant_id), 7);
+    assert_eq!(client.get_total_scaled(&grant_id), (12_345, 6_789_012));
+
+    set_timestamp(&env, 100);
+    assert_eq!(client.get_released_scaled(&grant_id), (123, 4_567_800));
+
+    assert_contract_error(
+        client.mock_all_auths().try_set_amount_scale(&grant_id, &39),
+        Error::InvalidAmount,
+    );
+    client.mock_all_auths().set_amount_scale(&grant_id, &38);
+    assert_eq!(client.get_total_scaled(&grant_id), (0, 123_456_789_012));
+}
+
+#[test]
+fn test_schedule_data_round_trips_through_math_types() {
+    let env = Env::default();
+    for curve in [VestingCurve::Linear, VestingCurve::Quadratic] {
+        assert_eq!(VestingCurve::from(VestingCurveData::from(curve)), curve);
+    }
+    for rounding in [Rounding::Floor, Rounding::Ceil] {
+        assert_eq!(Rounding::from(RoundingData::from(rounding)), rounding);
+    }
+    for portion in [Portion::Amount(u128::MAX), Portion::Bps(10_000)] {
+        assert_eq!(Portion::from(PortionData::from(portion)), portion);
+    }
+
+    let segments = [
+        Segment {
+            start_offset: 0,
+            duration: 0,
+            portion: Portion::Bps(2_500),
+        },
+        Segment {
+            start_offset: 100,
+            duration: 1_000,
+            portion: Portion::Bps(7_500),
+        },
+    ];
+    let schedule = Schedule::new(&segments).unwrap();
+    let data = VestingScheduleData::from_schedule(&env, &schedule);
+    assert_eq!(
+        data.segments.get(1).unwrap(),
+        ScheduleSegmentData {
+            start_offset: 100,
+            duration: 1_000,
+            portion: PortionData::Bps(7_500),
+        }
+    );
+    assert_eq!(Schedule::try_from(&data), Ok(schedule));
+
+    // The data form is unvalidated, so converting back applies Schedule::new's checks.
+    let mut overlapping = data.clone();
+    overlapping.segments.push_back(ScheduleSegmentData {
+        start_offset: 500,
+        duration: 0,
+        portion: PortionData::Bps(0),
+    });
+    assert_eq!(
+        Schedule::try_from(&overlapping),
+        Err(ScheduleError::Overlapping)
+    );
+    let mut too_many = VestingScheduleData {
+        segments: vec![&env],
+    };
+    for i in 0..9 {
+        too_many.segments.push_back(ScheduleSegmentData {
+            start_offset: i,
+            duration: 0,
+            portion: PortionData::Amount(1),
+        });
+    }
+    assert_eq!(
+        Schedule::try_from(&too_many),
+        Err(ScheduleError::TooManySegments)
+    );
+}
+
+#[test]
+fn test_create_grant_vested_segments_follows_schedule_math() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+
+    // 25% unlocks 100 after the start, the rest streams over the following 1_000.
+    let schedule = Schedule::new(&[
+        Segment {
+            start_offset: 100,
+            duration: 0,
+            portion: Portion::Bps(2_500),
+        },
+        Segment {
+            start_offset: 100,
+            duration: 1_000,
+            portion: Portion::Bps(7_500),
+        },
+    ])
+    .unwrap();
+    let data = VestingScheduleData::from_schedule(&env, &schedule);
+    let grant_id: u64 = 404;
+    client
+        .mock_all_auths()
+        .create_grant_vested_segments(&grant_id, &recipient, &1_000, &50, &data);
+    assert_eq!(client.get_vesting_tranches(&grant_id).len(), 2);
+
+    for now in [0, 149, 150, 151, 650, 1_149, 1_150, 2_000] {
+        set_timestamp(&env, now);
+        assert_eq!(
+            client.get_claimable_details(&grant_id).accrued as u128,
+            schedule.claimable_at(1_000, 50, now).unwrap()
+        );
+    }
+
+    let mut overlapping = data.clone();
+    overlapping.segments.push_back(ScheduleSegmentData {
+        start_offset: 0,
+        duration: 10,
+        portion: PortionData::Bps(0),
+    });
+    assert_contract_error(
+        client.mock_all_auths().try_create_grant_vested_segments(
+            &405,
+            &recipient,
+            &1_000,
+            &50,
+            &overlapping,
+        ),
+        Error::InvalidAmount,
+    );
+    let mut too_many = VestingScheduleData {
+        segments: vec![&env],
+    };
+    for i in 0..9 {
+        too_many.segments.push_back(ScheduleSegmentData {
+            start_offset: i,
+            duration: 0,
+            portion: PortionData::Amount(1),
+        });
+    }
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant_vested_segments(&405, &recipient, &9, &50, &too_many),
+        Error::TooManyTranches,
+    );
+    assert!(!client.grant_exists(&405));
+}
+
+#[test]
+fn test_schedule_data_survives_contract_storage() {
+    let env = Env::default();
+    let contract_id = env.register_contract(None, GrantContract);
+    let schedule = Schedule::new(&[
+        Segment {
+            start_offset: 0,
+            duration: 500,
+            portion: Portion::Amount(400),
+        },
+        Segment {
+            start_offset: 500,
+            duration: 1_000,
+            portion: Portion::Amount(600),
+        },
+    ])
+    .unwrap();
+
+    let reloaded = env.as_contract(&contract_id, || {
+        let key = symbol_short!("schedule");
+        env.storage()
+            .instance()
+            .set(&key, &VestingScheduleData::from_schedule(&env, &schedule));
+        let data: VestingScheduleData = env.storage().instance().get(&key).unwrap();
+        Schedule::try_from(&data).unwrap()
+    });
+    assert_eq!(reloaded, schedule);
+    assert_eq!(
+        reloaded.claimable_at(1_000, 10, 1_010),
+        schedule.claimable_at(1_000, 10, 1_010)
+    );
+    assert_eq!(reloaded.claimable_at(1_000, 10, 1_010), Ok(700));
+}
+
+#[test]
+fn test_create_grant_never_reuses_an_id() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let other = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000);
+
+    let grant_id: u64 = 351;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &10);
+    set_timestamp(&env, 30);
+    client.mock_all_auths().withdraw(&grant_id, &200);
+
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant(&grant_id, &other, &5_000, &1),
+        Error::GrantAlreadyExists,
+    );
+    let grant = client.get_grant(&grant_id);
+    assert_eq!(grant.recipient, recipient);
+    assert_eq!(grant.total_amount, 1_000);
+    assert_eq!(grant.withdrawn, 200);
+    assert_eq!(grant.claimable, 100);
+
+    // A cancelled grant keeps its id for audit.
+    client.mock_all_auths().cancel_grant(&grant_id);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant(&grant_id, &other, &5_000, &1),
+        Error::GrantAlreadyExists,
+    );
+    let grant = client.get_grant(&grant_id);
+    assert_eq!(grant.status, GrantStatus::Cancelled);
+    assert_eq!(grant.recipient, recipient);
+    assert_eq!(grant.total_amount, 1_000);
+    assert_eq!(grant.withdrawn, 200);
+}
+
+#[test]
+fn test_self_grants_allowed_by_default() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    assert!(client.get_allow_self_grants());
+
+    client
+        .mock_all_auths()
+        .create_grant(&352, &admin, &1_000, &10);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&353, &admin, &1_000, &0, &100, &0);
+    client
+        .mock_all_auths()
+        .create_grant(&354, &recipient, &1_000, &10);
+    client.mock_all_auths().update_recipient(&354, &admin);
+    assert_eq!(client.get_grant(&354).recipient, admin);
+}
+
+#[test]
+fn test_self_grants_rejected_when_disabled() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&355, &admin, &1_000, &10);
+    client.mock_all_auths().set_allow_self_grants(&false);
+    assert_eq!(env.auths()[0].0, admin);
+    assert!(!client.get_allow_self_grants());
+    // Self-grants made before the switch are left alone.
+    assert_eq!(client.get_grant(&355).recipient, admin);
+
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant(&356, &admin, &1_000, &10),
+        Error::SelfGrantNotAllowed,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant_vested(&356, &admin, &1_000, &0, &100, &0),
+        Error::SelfGrantNotAllowed,
+    );
+
+    // Nor can an existing grant be steered to the admin afterwards.
+    client
+        .mock_all_auths()
+        .create_grant(&356, &recipient, &1_000, &10);
+    assert_contract_error(
+        client.mock_all_auths().try_update_recipient(&356, &admin),
+        Error::SelfGrantNotAllowed,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_transfer_grantee_rights(&356, &admin),
+        Error::SelfGrantNotAllowed,
+    );
+    assert_eq!(client.get_grant(&356).recipient, recipient);
+
+    client.mock_all_auths().set_allow_self_grants(&true);
+    client.mock_all_auths().update_recipient(&356, &admin);
+    assert_eq!(client.get_grant(&356).recipient, admin);
+}
+
+#[test]
+fn test_claim_vested_receipt_reports_amount_after_fees() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let token_client = create_mock_token(&env, 100, &contract_id, 1_000);
+    let grant_token = token_client.address.clone();
+
+    let grant_id: u64 = 357;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &1_000, &0, &1_000, &0);
+
+    set_timestamp(&env, 400);
+    let receipt = client.mock_all_auths().claim_vested_with_receipt(&grant_id);
+    assert_eq!(env.auths()[0].0, recipient);
+    assert_eq!(
+        receipt,
+        WithdrawReceipt {
+            requested: 400,
+            transferred: 396,
+            new_released: 400,
+            timestamp: 400,
+        }
+    );
+    assert_eq!(token_client.balance(&recipient), 396);
+
+    set_timestamp(&env, 1_000);
+    assert_eq!(client.mock_all_auths().claim_vested(&grant_id), 600);
+    assert_eq!(token_client.balance(&recipient), 396 + 594);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_claim_vested_with_receipt(&grant_id),
+        Error::NothingToClaim,
+    );
+}
+
+#[test]
+fn test_withdraw_receipt_reports_amount_after_fees() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let token_client = create_mock_token(&env, 100, &contract_id, 1_000);
+
+    let grant_id: u64 = 410;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &token_client.address, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&grant_id, &recipient, &1_000, &1);
+
+    set_timestamp(&env, 400);
+    let receipt = client
+        .mock_all_auths()
+        .withdraw_with_receipt(&grant_id, &300);
+    assert_eq!(env.auths()[0].0, recipient);
+    assert_eq!(
+        receipt,
+        WithdrawReceipt {
+            requested: 300,
+            transferred: 297,
+            new_released: 300,
+            timestamp: 400,
+        }
+    );
+    assert_eq!(token_client.balance(&recipient), 297);
+    assert_eq!(token_client.balance(&contract_id), 700);
+
+    client.mock_all_auths().withdraw(&grant_id, &100);
+    assert_eq!(token_client.balance(&recipient), 297 + 99);
+    assert_eq!(client.get_grant(&grant_id).withdrawn, 400);
+}
+
+#[test]
+fn test_revoke_vesting_with_fee_token_keeps_accounting_exact() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let token_client = create_mock_token(&env, 250, &contract_id, 1_000);
+
+    let grant_id: u64 = 383;
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &token_client.address, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&grant_id, &recipient, &1_000, &0, &1_000, &0);
+
+    // The contract sends the full unvested amount; the token's 2.5% fee comes out of what
+    // the admin receives, not out of the grantee's share.
+    set_timestamp(&env, 400);
+    assert_eq!(client.mock_all_auths().revoke_vesting(&grant_id), 600);
+    assert_eq!(token_client.balance(&admin), 585);
+    assert_eq!(token_client.balance(&contract_id), 400);
+
+    let receipt = client.mock_all_auths().claim_vested_with_receipt(&grant_id);
+    assert_eq!(receipt.requested, 400);
+    assert_eq!(receipt.transferred, 390);
+    assert_eq!(token_client.balance(&recipient), 390);
+    assert_eq!(token_client.balance(&contract_id), 0);
+    assert_eq!(client.get_vesting_schedule(&grant_id).claimed, 400);
+}
+
+#[test]
+fn test_max_fee_bps_rejects_claims_losing_too_much_to_fees() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let token_client = create_mock_token(&env, 500, &contract_id, 1_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &token_client.address, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&384, &recipient, &1_000, &0, &1_000, &0);
+    assert_eq!(client.get_max_fee_bps(&384), None);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_set_max_fee_bps(&384, &Some(10_001)),
+        Error::InvalidAmount,
+    );
+    client.mock_all_auths().set_max_fee_bps(&384, &Some(100));
+    assert_eq!(client.get_max_fee_bps(&384), Some(100));
+
+    // A 5% fee against a 1% tolerance reverts the whole claim, transfer included.
+    set_timestamp(&env, 400);
+    assert_contract_error(
+        client.mock_all_auths().try_claim_vested(&384),
+        Error::ExcessiveFee,
+    );
+    assert_eq!(token_client.balance(&recipient), 0);
+    assert_eq!(token_client.balance(&contract_id), 1_000);
+    assert_eq!(client.get_vesting_schedule(&384).claimed, 0);
+
+    // Once the token charges less than the tolerance, the same claim goes through.
+    token_client.set_fee_bps(&50);
+    assert_eq!(client.mock_all_auths().claim_vested(&384), 400);
+    assert_eq!(token_client.balance(&recipient), 398);
+    assert_eq!(client.get_vesting_schedule(&384).claimed, 400);
+}
+
+#[test]
+fn test_payouts_reject_inconsistent_token_balances() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let token_client = create_mock_token(&env, 0, &contract_id, 1_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &token_client.address, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&397, &recipient, &1_000, &0, &1_000, &0);
+    set_timestamp(&env, 400);
+
+    // A 100% fee delivers nothing.
+    token_client.set_fee_bps(&10_000);
+    assert_contract_error(
+        client.mock_all_auths().try_claim_vested(&397),
+        Error::TransferFailed,
+    );
+    token_client.set_fee_bps(&0);
+
+    // The contract is debited nothing, or twice the amount.
+    for debit_bps in [0, 20_000] {
+        token_client.set_debit_bps(&debit_bps);
+        assert_contract_error(
+            client.mock_all_auths().try_claim_vested(&397),
+            Error::TransferFailed,
+        );
+        assert_contract_error(
+            client.mock_all_auths().try_revoke_vesting(&397),
+            Error::TransferFailed,
+        );
+    }
+
+    // Every failure rolled back, so an honest token pays the claim in full.
+    assert_eq!(token_client.balance(&contract_id), 1_000);
+    assert_eq!(client.get_vesting_schedule(&397).claimed, 0);
+    token_client.set_debit_bps(&10_000);
+    assert_eq!(client.mock_all_auths().claim_vested(&397), 400);
+    assert_eq!(token_client.balance(&recipient), 400);
+    assert_eq!(token_client.balance(&contract_id), 600);
+    assert_eq!(
+        client
+            .get_released_by_token(&397)
+            .get(token_client.address.clone()),
+        Some(400)
+    );
+}
+
+#[test]
+fn test_released_by_token_tracks_grant_and_bonus_tokens() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+    let bonus_token = create_token(&env, &admin, &admin, 500);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client.mock_all_auths().create_grant_with_bonus(
+        &398,
+        &recipient,
+        &1_000,
+        &10,
+        &500,
+        &bonus_token,
+    );
+    assert_eq!(client.get_released_by_token(&398).len(), 0);
+    assert_contract_error(client.try_get_released_by_token(&399), Error::GrantNotFound);
+
+    set_timestamp(&env, 40);
+    client.mock_all_auths().withdraw(&398, &400);
+    let released = client.get_released_by_token(&398);
+    assert_eq!(released.len(), 1);
+    assert_eq!(released.get(grant_token.clone()), Some(400));
+
+    // Completing the grant pays the bonus in the second token.
+    set_timestamp(&env, 100);
+    client.mock_all_auths().withdraw(&398, &600);
+    let released = client.get_released_by_token(&398);
+    assert_eq!(released.len(), 2);
+    assert_eq!(released.get(grant_token), Some(1_000));
+    assert_eq!(released.get(bonus_token), Some(500));
+}
+
+#[test]
+fn test_min_funding_bps_gates_grant_creation() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 400);
+    let token_admin = token::StellarAssetClient::new(&env, &grant_token);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    assert_eq!(client.get_min_funding_bps(), 0);
+    assert_contract_error(
+        client.mock_all_auths().try_set_min_funding_bps(&10_001),
+        Error::InvalidAmount,
+    );
+    client.mock_all_auths().set_min_funding_bps(&5_000);
+    assert_eq!(client.get_min_funding_bps(), 5_000);
+
+    // 40% funded falls short of the 50% requirement.
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant(&399, &recipient, &1_000, &10),
+        Error::InsufficientFunding,
+    );
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant_vested(&399, &recipient, &1_000, &0, &100, &0),
+        Error::InsufficientFunding,
+    );
+    assert!(!client.grant_exists(&399));
+
+    // At 50% the grant goes active.
+    token_admin.mock_all_auths().mint(&contract_id, &100);
+    client
+        .mock_all_auths()
+        .create_grant(&399, &recipient, &1_000, &10);
+    assert_eq!(client.get_grant(&399).status, GrantStatus::Active);
+
+    // The balance already backing grant 399 does not count toward the next grant.
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant(&403, &recipient, &200, &10),
+        Error::InsufficientFunding,
+    );
+    token_admin.mock_all_auths().mint(&contract_id, &599);
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_create_grant(&403, &recipient, &200, &10),
+        Error::InsufficientFunding,
+    );
+    token_admin.mock_all_auths().mint(&contract_id, &1);
+    client
+        .mock_all_auths()
+        .create_grant(&403, &recipient, &200, &10);
+    assert!(client.grant_exists(&403));
+}
+
+#[test]
+fn test_grant_creation_rejects_non_token_grant_token() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    // A contract without the token interface, and an address with no contract at all.
+    for grant_token in [
+        env.register_contract(None, MockOracle),
+        Address::generate(&env),
+    ] {
+        let contract_id = env.register_contract(None, GrantContract);
+        let client = GrantContractClient::new(&env, &contract_id);
+        set_timestamp(&env, 0);
+        client
+            .mock_all_auths()
+            .initialize(&admin, &grant_token, &treasury);
+
+        assert_contract_error(
+            client
+                .mock_all_auths()
+                .try_create_grant(&385, &recipient, &1_000, &10),
+            Error::InvalidToken,
+        );
+        assert_contract_error(
+            client
+                .mock_all_auths()
+                .try_create_grant_vested(&385, &recipient, &1_000, &0, &100, &0),
+            Error::InvalidToken,
+        );
+        assert!(!client.grant_exists(&385));
+        assert_eq!(client.get_token_decimals(), None);
+
+        // Exotic tokens can opt out of the check.
+        client.mock_all_auths().set_skip_token_validation(&true);
+        client
+            .mock_all_auths()
+            .create_grant(&385, &recipient, &1_000, &10);
+        assert_eq!(client.get_token_decimals(), None);
+    }
+}
+
+#[test]
+fn test_grant_creation_caches_grant_token_decimals() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+    let grant_token = register_token(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    assert_eq!(client.get_token_decimals(), None);
+    client
+        .mock_all_auths()
+        .create_grant(&386, &recipient, &1_000, &10);
+    assert_eq!(client.get_token_decimals(), Some(7));
+}
+
+#[test]
+fn test_admin_outstanding_sums_remaining_across_admin_grants() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    assert_eq!(client.get_admin_outstanding(&admin), 0);
+
+    client
+        .mock_all_auths()
+        .create_grant(&387, &recipient, &1_000, &10);
+    client
+        .mock_all_auths()
+        .create_grant(&388, &recipient, &2_000, &10);
+    client
+        .mock_all_auths()
+        .create_grant(&389, &recipient, &3_000, &10);
+    assert_eq!(client.get_admin_outstanding(&admin), 6_000);
+
+    set_timestamp(&env, 50);
+    client.mock_all_auths().withdraw(&387, &300);
+    client.mock_all_auths().withdraw(&389, &500);
+    assert_eq!(client.get_admin_outstanding(&admin), 700 + 2_000 + 2_500);
+    assert_eq!(client.get_admin_outstanding(&recipient), 0);
+
+    // Fully released but not fully withdrawn: the remainder is still owed.
+    set_timestamp(&env, 200);
+    client.mock_all_auths().withdraw(&387, &100);
+    assert_eq!(client.get_grant(&387).status, GrantStatus::Completed);
+    assert_eq!(client.get_admin_outstanding(&admin), 600 + 2_000 + 2_500);
+}
+
+#[test]
+fn test_grant_records_live_in_persistent_storage() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    for grant_id in 358..368 {
+        client
+            .mock_all_auths()
+            .create_grant(&grant_id, &recipient, &1_000, &10);
+    }
+    client
+        .mock_all_auths()
+        .create_grant_vested(&368, &recipient, &1_000, &0, &100, &0);
+    set_timestamp(&env, 10);
+    client.mock_all_auths().withdraw(&358, &50);
+
+    env.as_contract(&contract_id, || {
+        for grant_id in 358..368 {
+            let key = DataKey::Grant(grant_id);
+            assert!(env.storage().persistent().has(&key));
+            assert!(!env.storage().instance().has(&key));
+        }
+        assert!(env.storage().persistent().has(&DataKey::Vesting(368)));
+        assert!(!env.storage().instance().has(&DataKey::Vesting(368)));
+    });
+    assert_eq!(client.get_grant(&358).withdrawn, 50);
+    assert!(client.grant_exists(&367));
+    assert_eq!(client.get_vesting_schedule(&368).total_amount, 1_000);
+}
+
+#[test]
+fn test_migrate_storage_moves_legacy_records() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+    let grant_token = create_token(&env, &admin, &contract_id, 1_000_000);
+
+    set_timestamp(&env, 0);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    for grant_id in [369_u64, 370, 371] {
+        client
+            .mock_all_auths()
+            .create_grant(&grant_id, &recipient, &1_000, &10);
+    }
+
+    // Rewind to the schema 1 layout: grants and the id list in instance storage, and none of
+    // the records or indexes schema 2 added.
+    env.as_contract(&contract_id, || {
+        let persistent = env.storage().persistent();
+        let instance = env.storage().instance();
+        for grant_id in [369_u64, 370, 371] {
+            let grant: Grant = persistent.get(&DataKey::Grant(grant_id)).unwrap();
+            for key in grant_record_keys(grant_id) {
+                persistent.remove(&key);
+            }
+            instance.set(&DataKey::Grant(grant_id), &grant);
+        }
+        let ids: soroban_sdk::Vec<u64> = persistent.get(&DataKey::GrantIds).unwrap();
+        persistent.remove(&DataKey::GrantIds);
+        instance.set(&DataKey::GrantIds, &ids);
+        persistent.remove(&DataKey::AdminGrants(admin.clone()));
+        persistent.remove(&DataKey::GranteeGrants(recipient.clone()));
+        persistent.remove(&DataKey::GrantsByStatus(GrantStatus::Active));
+        persistent.remove(&DataKey::TokenStats(grant_token.clone()));
+        instance.remove(&DataKey::SchemaVersion);
+    });
+    assert_eq!(client.schema_version(), 1);
+
+    // Legacy grants can be read before the migration runs, but not changed.
+    set_timestamp(&env, 10);
+    assert_eq!(client.get_grant(&371).claimable, 100);
+    assert_eq!(client.list_grants(&0, &10, &false).len(), 3);
+    assert_contract_error(
+        client.mock_all_auths().try_withdraw(&369, &100),
+        Error::InvalidState,
+    );
+    // The allocated total is not known until every grant has been counted.
+    assert_contract_error(
+        client
+            .mock_all_auths()
+            .try_rescue_tokens(&grant_token, &1, &admin),
+        Error::InvalidState,
+    );
+
+    assert_eq!(client.mock_all_auths().migrate_storage(&0, &2), 1);
+    assert_eq!(env.auths()[0].0, admin);
+    assert_eq!(client.schema_version(), 1);
+    assert_eq!(client.mock_all_auths().migrate_storage(&2, &2), 0);
+    assert_eq!(client.schema_version(), SCHEMA_VERSION);
+    // Repeating a batch is harmless.
+    assert_eq!(client.mock_all_auths().migrate_storage(&0, &50), 0);
+
+    env.as_contract(&contract_id, || {
+        for grant_id in [369_u64, 370, 371] {
+            assert!(env.storage().persistent().has(&DataKey::Grant(grant_id)));
+            assert!(!env.storage().instance().has(&DataKey::Grant(grant_id)));
+        }
+        assert!(env.storage().persistent().has(&DataKey::GrantIds));
+        assert!(!env.storage().instance().has(&DataKey::GrantIds));
+    });
+    // The indexes and token stats schema 1 lacked are built as the grants move.
+    let ids = vec![&env, 369_u64, 370, 371];
+    assert_eq!(client.get_grants_by_admin(&admin, &0, &10), ids);
+    assert_eq!(client.get_grants_by_grantee(&recipient, &0, &10), ids);
+    assert_eq!(
+        client.get_grants_by_status(&GrantStatus::Active, &0, &10),
+        ids
+    );
+    assert_eq!(client.get_token_stats(&grant_token).escrowed, 3_000);
+
+    client.mock_all_auths().withdraw(&369, &100);
+    assert_eq!(client.get_grant(&369).withdrawn, 100);
+    assert_eq!(client.get_grant(&371).claimable, 100);
+    assert_eq!(client.get_token_stats(&grant_token).escrowed, 2_900);
+}
+
+#[test]
+fn test_bump_grant_keeps_dormant_grant_alive() {
+    let env = Env::default();
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let grant_token = register_token(&env);
+    let treasury = Address::generate(&env);
+
+    let contract_id = env.register_contract(None, GrantContract);
+    let client = GrantContractClient::new(&env, &contract_id);
+
+    set_timestamp(&env, 0);
+    set_sequence(&env, 100);
+    client
+        .mock_all_auths()
+        .initialize(&admin, &grant_token, &treasury);
+    client
+        .mock_all_auths()
+        .create_grant(&372, &recipient, &1_000, &0);
+    client
+        .mock_all_auths()
+        .create_grant_vested(&373, &recipient, &1_000, &0, &100, &0);
+    client
+        .mock_all_auths()
+        .create_grant(&375, &recipient, &1_000, &0);
+    assert_contract_error(client.try_bump_grant(&374, &1_000), Error::GrantNotFound);
+
+    // Requests beyond the network maximum are capped.
+    assert_eq!(client.bump_grant(&372, &u32::MAX), 6_312_000);
+    assert_eq!(client.bump_grant(&373, &10_000), 10_000);
+    assert!(env.auths().is_empty());
+    client.bump_contract();
+
+    // Well past the TTL set at creation: only the bumps keep the records readable.
+    set_sequence(&env, 6_000_000);
+    client.bump_contract();
+    assert_eq!(client.bump_grant(&372, &u32::MAX), 6_312_000);
+    assert_eq!(client.bump_grant(&373, &u32::MAX), 6_312_000);
+    // Reading a record close to expiry extends it as well.
+    assert_eq!(client.get_grant(&375).total_amount, 1_000);
+    set_sequence(&env, 12_000_000);
+    client.bump_contract();
+    assert_eq!(client.get_grant(&372).total_amount, 1_000);
+    assert_eq!(client.get_vesting_schedule(&373).total_amount, 1_000);
+    assert_eq!(client.get_grant(&375).total_amount, 1_000);
+    // The grant's other records were extended along with it.
+    assert_eq!(client.get_history(&372, &0, &10).len(), 1);
+    assert_eq!(client.get_history(&373, &0, &10).len(), 1);
+}
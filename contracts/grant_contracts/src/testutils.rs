@@ -0,0 +1,134 @@
+//! Test helpers, available to this crate's tests and, with the `testutils` feature, to
+//! downstream crates testing against the grant contract.
+
+use soroban_sdk::{contract, contractimpl, contracttype, Address, Env, Symbol, Val, Vec};
+
+use crate::vesting::bps_of;
+
+#[derive(Clone)]
+#[contracttype]
+enum MockTokenKey {
+    Balance(Address),
+    FeeBps,
+    DebitBps,
+    FailTransfers,
+    Reenter,
+}
+
+/// Minimal token with configurable misbehavior, for paths the Stellar asset contract cannot
+/// exercise: a per-transfer fee, a sender debit that does not match the amount, failing
+/// transfers, and a call back into another contract from inside `transfer`.
+#[contract]
+pub struct MockToken;
+
+#[contractimpl]
+impl MockToken {
+    pub fn mint(env: Env, to: Address, amount: i128) {
+        let balance = Self::balance(env.clone(), to.clone());
+        env.storage()
+            .instance()
+            .set(&MockTokenKey::Balance(to), &(balance + amount));
+    }
+
+    pub fn balance(env: Env, id: Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&MockTokenKey::Balance(id))
+            .unwrap_or(0)
+    }
+
+    pub fn decimals(_env: Env) -> u32 {
+        7
+    }
+
+    /// Moves `amount` out of `from`, burning `fee_bps` of it, so `to` receives the rest. With
+    /// `debit_bps` set, `from` is debited that share of `amount` instead.
+    pub fn transfer(env: Env, from: Address, to: Address, amount: i128) {
+        from.require_auth();
+        if Self::fail_transfers(env.clone()) {
+            panic!("transfers are disabled");
+        }
+        let from_balance = Self::balance(env.clone(), from.clone());
+        if amount < 0 || from_balance < amount {
+            panic!("insufficient balance");
+        }
+        let debit = amount * i128::from(Self::debit_bps(env.clone())) / 10_000;
+        env.storage()
+            .instance()
+            .set(&MockTokenKey::Balance(from), &(from_balance - debit));
+        // amount is non-negative here, so the casts are lossless.
+        let fee = bps_of(amount as u128, Self::fee_bps(env.clone())) as i128;
+        Self::mint(env.clone(), to, amount - fee);
+
+        let reenter: Option<(Address, Symbol, Vec<Val>)> =
+            env.storage().instance().get(&MockTokenKey::Reenter);
+        if let Some((contract, function, args)) = reenter {
+            env.invoke_contract::<Val>(&contract, &function, args);
+        }
+    }
+
+    /// Share of every transfer burned, in basis points (capped at 100%).
+    pub fn set_fee_bps(env: Env, fee_bps: u32) {
+        env.storage()
+            .instance()
+            .set(&MockTokenKey::FeeBps, &fee_bps);
+    }
+
+    pub fn fee_bps(env: Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&MockTokenKey::FeeBps)
+            .unwrap_or(0)
+    }
+
+    /// Share of the amount taken from the sender, in basis points; 10_000 (the default) is
+    /// an honest debit, less leaves the sender's balance too high and more overdraws it.
+    pub fn set_debit_bps(env: Env, debit_bps: u32) {
+        env.storage()
+            .instance()
+            .set(&MockTokenKey::DebitBps, &debit_bps);
+    }
+
+    pub fn debit_bps(env: Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&MockTokenKey::DebitBps)
+            .unwrap_or(10_000)
+    }
+
+    /// When true, every transfer panics.
+    pub fn set_fail_transfers(env: Env, fail: bool) {
+        env.storage()
+            .instance()
+            .set(&MockTokenKey::FailTransfers, &fail);
+    }
+
+    pub fn fail_transfers(env: Env) -> bool {
+        env.storage()
+            .instance()
+            .get(&MockTokenKey::FailTransfers)
+            .unwrap_or(false)
+    }
+
+    /// Contract function, and its arguments, that `transfer` calls after moving the funds;
+    /// None stops the call. The host rejects calls back into a contract already on the call
+    /// stack, so pointing this at the caller shows how a reentrant token is handled.
+    pub fn set_reenter(env: Env, target: Option<(Address, Symbol, Vec<Val>)>) {
+        env.storage()
+            .instance()
+            .set(&MockTokenKey::Reenter, &target);
+    }
+}
+
+/// Registers a MockToken charging `fee_bps` on transfers and mints `amount` of it to `to`.
+pub fn create_mock_token<'a>(
+    env: &Env,
+    fee_bps: u32,
+    to: &Address,
+    amount: i128,
+) -> MockTokenClient<'a> {
+    let client = MockTokenClient::new(env, &env.register_contract(None, MockToken));
+    client.set_fee_bps(&fee_bps);
+    client.mint(to, &amount);
+    client
+}
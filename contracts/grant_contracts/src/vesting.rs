@@ -0,0 +1,557 @@
+//! Pure vesting schedule math, independent of contract storage.
+
+const LOW_64: u128 = u64::MAX as u128;
+
+/// Full 256-bit product of `a` and `b` as (high, low) 128-bit halves.
+fn mul_wide(a: u128, b: u128) -> (u128, u128) {
+    let (a_hi, a_lo) = (a >> 64, a & LOW_64);
+    let (b_hi, b_lo) = (b >> 64, b & LOW_64);
+
+    let lo_lo = a_lo * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let hi_lo = a_hi * b_lo;
+    let hi_hi = a_hi * b_hi;
+
+    // At most 3 * (2^64 - 1), so the sum of the middle terms cannot overflow.
+    let mid = (lo_lo >> 64) + (lo_hi & LOW_64) + (hi_lo & LOW_64);
+    let lo = (lo_lo & LOW_64) | (mid << 64);
+    let hi = hi_hi + (lo_hi >> 64) + (hi_lo >> 64) + (mid >> 64);
+    (hi, lo)
+}
+
+/// `a * b / denominator` rounded down, computed with a 256-bit intermediate so it is exact
+/// for all inputs. Returns None for a zero denominator or a quotient above u128::MAX.
+pub fn mul_div_floor(a: u128, b: u128, denominator: u128) -> Option<u128> {
+    if denominator == 0 {
+        return None;
+    }
+    let (hi, lo) = mul_wide(a, b);
+    if hi == 0 {
+        return Some(lo / denominator);
+    }
+    if hi >= denominator {
+        return None;
+    }
+
+    // Schoolbook binary long division of (hi, lo) by the denominator; hi is the running
+    // remainder and stays below the denominator.
+    let mut remainder = hi;
+    let mut quotient: u128 = 0;
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((lo >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            quotient |= 1;
+        }
+    }
+    Some(quotient)
+}
+
+/// `a * b / denominator` rounded up. Returns None for a zero denominator or a quotient above
+/// u128::MAX.
+pub fn mul_div_ceil(a: u128, b: u128, denominator: u128) -> Option<u128> {
+    let quotient = mul_div_floor(a, b, denominator)?;
+    if mul_wide(quotient, denominator) == mul_wide(a, b) {
+        Some(quotient)
+    } else {
+        quotient.checked_add(1)
+    }
+}
+
+/// Basis points in a whole: 10_000 bps is 100%.
+pub const BPS_DENOMINATOR: u32 = 10_000;
+
+/// Why a basis-point value was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BpsError {
+    /// A share, or a sum of shares, is above BPS_DENOMINATOR.
+    OutOfRange,
+}
+
+/// `bps` if it is at most 100%.
+pub fn validate_bps(bps: u32) -> Result<u32, BpsError> {
+    if bps > BPS_DENOMINATOR {
+        return Err(BpsError::OutOfRange);
+    }
+    Ok(bps)
+}
+
+/// Sum of `shares` if it is at most 100%, for splitting one amount several ways.
+pub fn validate_bps_total(shares: &[u32]) -> Result<u32, BpsError> {
+    shares.iter().try_fold(0_u32, |total, &bps| {
+        validate_bps(total.checked_add(bps).ok_or(BpsError::OutOfRange)?)
+    })
+}
+
+/// `amount * bps / 10_000` rounded down. `bps` above 100% is treated as 100%, so the result
+/// never exceeds `amount` and never overflows, even for u128::MAX.
+pub fn bps_of(amount: u128, bps: u32) -> u128 {
+    let bps = u128::from(bps.min(BPS_DENOMINATOR));
+    let denominator = u128::from(BPS_DENOMINATOR);
+    // Split amount into whole multiples of the denominator and a remainder below it, so
+    // neither product can overflow.
+    amount / denominator * bps + amount % denominator * bps / denominator
+}
+
+/// `amount` split into (bps_of(amount, bps), the rest). The two parts always sum to `amount`.
+pub fn split_bps(amount: u128, bps: u32) -> (u128, u128) {
+    let share = bps_of(amount, bps);
+    (share, amount - share)
+}
+
+/// Why a vesting computation could not produce an amount.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VestingError {
+    /// The schedule ends, or its cliff falls, beyond u64::MAX, or the arithmetic overflowed.
+    Overflow,
+}
+
+/// Shape of the vesting curve between a schedule's start and end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VestingCurve {
+    /// Vests at a constant rate: `total * elapsed / duration`.
+    Linear,
+    /// Back-loaded: `total * elapsed² / duration²`, so half the duration vests a quarter.
+    Quadratic,
+}
+
+/// Which way a vested amount between two whole units is rounded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rounding {
+    #[default]
+    Floor,
+    /// Rounds up, but never above `total`; the schedule still ends at exactly `total`.
+    Ceil,
+}
+
+/// Amount of `total` vested at `now` for a schedule following `curve` from `start` for
+/// `duration` seconds. Nothing is vested before `start`; everything is vested once
+/// `duration` has elapsed, so a zero duration vests the full amount at `start` rather than
+/// being an error. Rounds as `rounding` says and is exact for every input. The amount never
+/// decreases over time, so claiming the difference each time distributes exactly `total`.
+/// Fails with Overflow when `start + duration` is not a representable timestamp.
+pub fn try_compute_claimable_curve(
+    total: u128,
+    start: u64,
+    now: u64,
+    duration: u64,
+    curve: VestingCurve,
+    rounding: Rounding,
+) -> Result<u128, VestingError> {
+    start.checked_add(duration).ok_or(VestingError::Overflow)?;
+    if now < start {
+        return Ok(0);
+    }
+    let elapsed = now - start;
+    if elapsed >= duration {
+        return Ok(total);
+    }
+
+    // elapsed < duration, so the quotient is at most total and always fits; both squares
+    // fit in u128 because the operands are u64.
+    let (elapsed, duration) = (u128::from(elapsed), u128::from(duration));
+    let (numerator, denominator) = match curve {
+        VestingCurve::Linear => (elapsed, duration),
+        VestingCurve::Quadratic => (elapsed * elapsed, duration * duration),
+    };
+    let quotient = match rounding {
+        Rounding::Floor => mul_div_floor(total, numerator, denominator),
+        Rounding::Ceil => mul_div_ceil(total, numerator, denominator),
+    };
+    quotient.ok_or(VestingError::Overflow)
+}
+
+/// Infallible form of try_compute_claimable_curve; reports nothing vested on error.
+pub fn compute_claimable_curve(
+    total: u128,
+    start: u64,
+    now: u64,
+    duration: u64,
+    curve: VestingCurve,
+    rounding: Rounding,
+) -> u128 {
+    try_compute_claimable_curve(total, start, now, duration, curve, rounding).unwrap_or(0)
+}
+
+/// compute_claimable_balance with an explicit rounding mode.
+pub fn compute_claimable_balance_rounded(
+    total: u128,
+    start: u64,
+    now: u64,
+    duration: u64,
+    rounding: Rounding,
+) -> u128 {
+    compute_claimable_curve(total, start, now, duration, VestingCurve::Linear, rounding)
+}
+
+/// try_compute_claimable_curve for the linear curve, rounding down.
+pub fn try_compute_claimable_balance(
+    total: u128,
+    start: u64,
+    now: u64,
+    duration: u64,
+) -> Result<u128, VestingError> {
+    try_compute_claimable_curve(
+        total,
+        start,
+        now,
+        duration,
+        VestingCurve::Linear,
+        Rounding::Floor,
+    )
+}
+
+/// Infallible form of try_compute_claimable_balance; reports nothing vested on error.
+pub fn compute_claimable_balance(total: u128, start: u64, now: u64, duration: u64) -> u128 {
+    try_compute_claimable_balance(total, start, now, duration).unwrap_or(0)
+}
+
+/// Like try_compute_claimable_curve, but nothing is vested before `start + cliff_duration`.
+/// At the cliff everything accrued since `start` unlocks at once. A cliff at or beyond
+/// `duration` vests the full amount at the cliff; a zero cliff is identical to the plain
+/// schedule. Fails with Overflow when the cliff is not a representable timestamp.
+pub fn try_compute_claimable_curve_with_cliff(
+    total: u128,
+    start: u64,
+    cliff_duration: u64,
+    now: u64,
+    duration: u64,
+    curve: VestingCurve,
+    rounding: Rounding,
+) -> Result<u128, VestingError> {
+    let cliff_end = start
+        .checked_add(cliff_duration)
+        .ok_or(VestingError::Overflow)?;
+    if now < cliff_end {
+        // Still validate the schedule end so a bad schedule fails before its cliff too.
+        start.checked_add(duration).ok_or(VestingError::Overflow)?;
+        return Ok(0);
+    }
+    try_compute_claimable_curve(total, start, now, duration, curve, rounding)
+}
+
+/// try_compute_claimable_curve_with_cliff for the linear curve, rounding down.
+pub fn try_compute_claimable_balance_with_cliff(
+    total: u128,
+    start: u64,
+    cliff_duration: u64,
+    now: u64,
+    duration: u64,
+) -> Result<u128, VestingError> {
+    try_compute_claimable_curve_with_cliff(
+        total,
+        start,
+        cliff_duration,
+        now,
+        duration,
+        VestingCurve::Linear,
+        Rounding::Floor,
+    )
+}
+
+/// Infallible form of try_compute_claimable_balance_with_cliff; reports nothing vested on
+/// error.
+pub fn compute_claimable_balance_with_cliff(
+    total: u128,
+    start: u64,
+    cliff_duration: u64,
+    now: u64,
+    duration: u64,
+) -> u128 {
+    try_compute_claimable_balance_with_cliff(total, start, cliff_duration, now, duration)
+        .unwrap_or(0)
+}
+
+/// `now` shifted back by `paused_seconds` of frozen time since `start`. Never moves before
+/// `start`, so a pause longer than the elapsed time leaves the schedule at its beginning.
+pub fn active_time(start: u64, now: u64, paused_seconds: u64) -> u64 {
+    if now <= start {
+        return now;
+    }
+    start + (now - start).saturating_sub(paused_seconds)
+}
+
+/// Like compute_claimable_balance, but the schedule does not advance during the
+/// `paused_seconds` it has spent paused since `start`.
+pub fn compute_claimable_excluding_pauses(
+    total: u128,
+    start: u64,
+    now: u64,
+    duration: u64,
+    paused_seconds: u64,
+) -> u128 {
+    compute_claimable_balance(
+        total,
+        start,
+        active_time(start, now, paused_seconds),
+        duration,
+    )
+}
+
+/// Earliest timestamp at which compute_claimable_balance reaches `target`: `start` for a zero
+/// target, None when `target` exceeds `total` or the schedule end is not representable.
+pub fn time_to_vest(total: u128, start: u64, duration: u64, target: u128) -> Option<u64> {
+    if target > total {
+        return None;
+    }
+    start.checked_add(duration)?;
+    if target == 0 || duration == 0 {
+        return Some(start);
+    }
+
+    // Smallest elapsed with floor(total * elapsed / duration) >= target, which is
+    // ceil(target * duration / total). The floor quotient is at most duration.
+    let mut elapsed = mul_div_floor(target, u128::from(duration), total)? as u64;
+    if compute_claimable_balance(total, start, start + elapsed, duration) < target {
+        elapsed += 1;
+    }
+    Some(start + elapsed)
+}
+
+/// Most samples preview_schedule produces, to keep on-chain previews within instruction limits.
+pub const MAX_PREVIEW_POINTS: u32 = 100;
+
+/// `points` evenly spaced timestamps from `start` to `start + duration` inclusive, rounded
+/// down. `points` is clamped to 2..=MAX_PREVIEW_POINTS so both ends are always sampled.
+pub fn sample_times(start: u64, duration: u64, points: u32) -> impl Iterator<Item = u64> {
+    let points = points.clamp(2, MAX_PREVIEW_POINTS);
+    let last = u128::from(points - 1);
+    (0..points).map(move |i| {
+        // The offset is at most duration, so it fits back into u64.
+        let offset = mul_div_floor(u128::from(duration), u128::from(i), last).unwrap_or(0);
+        start.saturating_add(offset as u64)
+    })
+}
+
+/// The unlock table for charts: compute_claimable_balance at each of sample_times. Yields
+/// pairs rather than collecting them since this module does not allocate.
+pub fn preview_schedule(
+    total: u128,
+    start: u64,
+    duration: u64,
+    points: u32,
+) -> impl Iterator<Item = (u64, u128)> {
+    sample_times(start, duration, points)
+        .map(move |now| (now, compute_claimable_balance(total, start, now, duration)))
+}
+
+/// Amount of `total` vested at `now` when it unlocks in `num_periods` equal steps, one at the
+/// end of each `period` seconds after `start`. Each step releases `total / num_periods` and the
+/// final step also releases the rounding remainder, so the full `total` is vested exactly at
+/// `start + period * num_periods`. A zero `period` or zero `num_periods` vests everything at
+/// `start`.
+pub fn compute_claimable_stepped(
+    total: u128,
+    start: u64,
+    now: u64,
+    period: u64,
+    num_periods: u32,
+) -> u128 {
+    if now < start {
+        return 0;
+    }
+    if period == 0 || num_periods == 0 {
+        return total;
+    }
+
+    let completed = (now - start) / period;
+    if completed >= u64::from(num_periods) {
+        return total;
+    }
+    total / u128::from(num_periods) * u128::from(completed)
+}
+
+/// Most segments a Schedule can hold.
+pub const MAX_SEGMENTS: usize = 8;
+
+/// How much of the grant a segment releases.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Portion {
+    /// A fixed token amount.
+    Amount(u128),
+    /// A share of the grant total in basis points.
+    Bps(u32),
+}
+
+/// One piece of a Schedule: releases `portion` linearly over `duration` seconds starting
+/// `start_offset` seconds after the schedule start. A zero duration releases it all at once.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Segment {
+    pub start_offset: u64,
+    pub duration: u64,
+    pub portion: Portion,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScheduleError {
+    Empty,
+    TooManySegments,
+    /// A segment starts before the previous one ends.
+    Overlapping,
+    /// Amount and Bps portions were mixed in one schedule.
+    MixedPortions,
+    /// Bps portions do not add up to 10_000.
+    BpsNotFull,
+    /// Amount portions do not add up to the grant total.
+    AmountMismatch,
+    MathOverflow,
+}
+
+/// A vesting schedule made of ordered, non-overlapping segments.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Schedule {
+    segments: [Segment; MAX_SEGMENTS],
+    len: usize,
+}
+
+impl Schedule {
+    /// Validates that segments are ordered, do not overlap and use one kind of portion, with
+    /// Bps portions summing to exactly 10_000.
+    pub fn new(segments: &[Segment]) -> Result<Schedule, ScheduleError> {
+        if segments.is_empty() {
+            return Err(ScheduleError::Empty);
+        }
+        if segments.len() > MAX_SEGMENTS {
+            return Err(ScheduleError::TooManySegments);
+        }
+
+        let mut bps_total: u32 = 0;
+        let mut previous_end: u64 = 0;
+        for (i, segment) in segments.iter().enumerate() {
+            if i > 0 && segment.start_offset < previous_end {
+                return Err(ScheduleError::Overlapping);
+            }
+            previous_end = segment
+                .start_offset
+                .checked_add(segment.duration)
+                .ok_or(ScheduleError::MathOverflow)?;
+
+            match (segment.portion, segments[0].portion) {
+                (Portion::Bps(bps), Portion::Bps(_)) => {
+                    bps_total = bps_total
+                        .checked_add(bps)
+                        .ok_or(ScheduleError::BpsNotFull)?;
+                }
+                (Portion::Amount(_), Portion::Amount(_)) => {}
+                _ => return Err(ScheduleError::MixedPortions),
+            }
+        }
+        if matches!(segments[0].portion, Portion::Bps(_)) && bps_total != 10_000 {
+            return Err(ScheduleError::BpsNotFull);
+        }
+
+        let mut schedule = Schedule {
+            segments: [segments[0]; MAX_SEGMENTS],
+            len: segments.len(),
+        };
+        schedule.segments[..segments.len()].copy_from_slice(segments);
+        Ok(schedule)
+    }
+
+    /// The plain linear schedule of compute_claimable_balance as a one-segment Schedule.
+    pub fn linear(duration: u64) -> Schedule {
+        let segment = Segment {
+            start_offset: 0,
+            duration,
+            portion: Portion::Bps(10_000),
+        };
+        Schedule {
+            segments: [segment; MAX_SEGMENTS],
+            len: 1,
+        }
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments[..self.len]
+    }
+
+    /// Amount of `total` vested at `now` for a schedule starting at `start`, summed over all
+    /// segments. Bps portions round down, with the last segment taking the remainder so the
+    /// full `total` vests when the schedule ends.
+    pub fn claimable_at(&self, total: u128, start: u64, now: u64) -> Result<u128, ScheduleError> {
+        let amounts = self.segment_amounts(total)?;
+        let mut vested: u128 = 0;
+        for (segment, amount) in self.segments().iter().zip(amounts) {
+            // A segment starting beyond the representable timeline never vests.
+            if let Some(segment_start) = start.checked_add(segment.start_offset) {
+                vested += compute_claimable_balance(amount, segment_start, now, segment.duration);
+            }
+        }
+        Ok(vested)
+    }
+
+    /// The amount of `total` each segment releases, in segment order; entries past the
+    /// schedule's segments are zero. Fails with AmountMismatch unless they add up to `total`.
+    pub fn segment_amounts(&self, total: u128) -> Result<[u128; MAX_SEGMENTS], ScheduleError> {
+        let mut amounts = [0; MAX_SEGMENTS];
+        let mut allocated: u128 = 0;
+        for (i, segment) in self.segments().iter().enumerate() {
+            let amount = match segment.portion {
+                Portion::Amount(amount) => amount,
+                Portion::Bps(_) if i + 1 == self.len => total - allocated,
+                Portion::Bps(bps) => bps_of(total, bps),
+            };
+            allocated = allocated
+                .checked_add(amount)
+                .ok_or(ScheduleError::AmountMismatch)?;
+            amounts[i] = amount;
+        }
+        if allocated != total {
+            return Err(ScheduleError::AmountMismatch);
+        }
+        Ok(amounts)
+    }
+}
+
+/// Why a claim could not be recorded against a ClaimState.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClaimError {
+    ZeroAmount,
+    /// The claim would take the total claimed past what has accrued.
+    ExceedsAccrued,
+}
+
+/// Running total claimed from one schedule. It only grows, and never past what has accrued,
+/// so it never exceeds the grant total either.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClaimState {
+    claimed: u128,
+}
+
+impl ClaimState {
+    /// Resumes from an amount already claimed.
+    pub fn new(claimed: u128) -> ClaimState {
+        ClaimState { claimed }
+    }
+
+    pub fn claimed(&self) -> u128 {
+        self.claimed
+    }
+
+    /// Accrued but unclaimed amount, given `accrued` has vested so far. Zero rather than
+    /// negative if `accrued` is below what was already claimed.
+    pub fn claimable_of(&self, accrued: u128) -> u128 {
+        accrued.saturating_sub(self.claimed)
+    }
+
+    /// claimable_of for the linear schedule of compute_claimable_balance.
+    pub fn claimable_now(&self, total: u128, start: u64, now: u64, duration: u64) -> u128 {
+        self.claimable_of(compute_claimable_balance(total, start, now, duration))
+    }
+
+    /// Records `amount` as claimed, given `accrued` (at most the grant total) has vested so
+    /// far. Leaves the state unchanged on error.
+    pub fn record_claim(&mut self, amount: u128, accrued: u128) -> Result<(), ClaimError> {
+        if amount == 0 {
+            return Err(ClaimError::ZeroAmount);
+        }
+        if amount > self.claimable_of(accrued) {
+            return Err(ClaimError::ExceedsAccrued);
+        }
+        // claimed + amount <= accrued, so this cannot overflow.
+        self.claimed += amount;
+        Ok(())
+    }
+}